@@ -1,48 +1,139 @@
-use lazy_static::lazy_static;
 use retour::static_detour;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::ops::RangeInclusive;
 use std::ptr;
-use std::sync::atomic::AtomicU64;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{
-        atomic::{AtomicU16, Ordering},
-        OnceLock, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        LazyLock, Mutex, OnceLock, RwLock,
     },
 };
-use tungstenite::Message;
+use thiserror::Error;
 use widestring::{U16CStr, U16CString};
 
-use crate::util::{get_game_base, OutgoingMessage, GAMEPUSH_SEND};
+use crate::messagetext;
+use crate::messagevisual::{VisualRequest, STANDARD_VISUAL};
+use crate::util::{
+    distance, get_game_base, send_message, unix_time, BloodMessageInfo, ExpiryReason,
+    OutgoingMessage, ReadTrigger, WorldMessageInfo,
+};
 use crate::{
-    player::{MapId, WorldChrMan},
+    player::{get_camera, MapId, WorldChrMan},
     reflection::{get_instance, DLRFLocatable},
 };
 
-// Despawn the message and remove the message text entry
+// Despawn the message(s) with the given text and remove the message text entry. Journaled
+// copies in maps that aren't loaded are dropped too, so they don't come back.
 pub fn delete_message(message: &str) {
+    log::info!("Removing message {message:?}");
+
+    delete_messages(|id| message_text_matches(id, message));
+    forget_saved_messages(|_, saved| normalized_is_equal(&saved.text, message));
+}
+
+// Despawn the message with the given handle and remove the message text entry
+pub fn delete_message_by_id(id: u16) {
+    log::info!("Removing message with id {id}");
+
+    let removed = delete_messages(|template| template == id && has_message(template));
+    let forgotten = forget_saved_messages(|saved_id, _| saved_id == id);
+    if removed.unwrap_or(0) == 0 && forgotten == 0 {
+        log::info!("No message with id {id} to remove");
+        send_message(OutgoingMessage::CommandFailed {
+            command: "RemoveBloodMessageById".to_string(),
+            reason: format!("no message with id {id}"),
+        });
+    }
+}
+
+// Despawn every message spawned with the given tag and remove their message text entries
+pub fn delete_messages_by_tag(tag: &str) {
+    log::info!("Removing messages tagged {tag:?}");
+
+    delete_messages(|id| message_has_tag(id, tag));
+    forget_saved_messages(|_, saved| saved.tag.as_deref() == Some(tag));
+}
+
+// Swaps the text of a message in place, the message stays where it is
+pub fn update_message(id: u16, text: &str) {
+    log::info!("Updating message {id} to {text:?}");
+
+    let rendered = U16CString::from_str_truncate(messagetext::render(text));
+
+    let live = {
+        let mut table = MESSAGE_TABLE
+            .get_or_init(Default::default)
+            .write()
+            .expect("Could not acquire message table write lock");
+
+        match table.get_mut(&id) {
+            Some(entry) => {
+                entry.saved.text = text.to_string();
+                entry.previous_text = std::mem::replace(&mut entry.text, rendered);
+                true
+            }
+            None => false,
+        }
+    };
+
+    //also covers messages that aren't loaded right now
+    let saved = {
+        let mut journal = MESSAGE_JOURNAL.lock().unwrap();
+        let saved = journal.get_mut(&id).map(|saved| {
+            saved.text = text.to_string();
+            saved.clone()
+        });
+        if saved.is_some() {
+            mark_journal_dirty();
+        }
+        saved
+    };
+
+    if !live && saved.is_none() {
+        log::info!("No message with id {id} to update");
+        send_message(OutgoingMessage::CommandFailed {
+            command: "UpdateBloodMessage".to_string(),
+            reason: format!("no message with id {id}"),
+        });
+    }
+}
+
+// Drop a message from the journal so it never comes back, despawning it if it's currently loaded.
+// Unlike the remove commands this also works for messages in maps that aren't loaded right now.
+pub fn forget_message(id: u16) {
+    log::info!("Forgetting message with id {id}");
+
+    delete_messages(|template| template == id && has_message(template));
+    forget_saved_message(id);
+}
+
+// Despawn every message whose template id matches, and remove the message text entries.
+// Removed messages are dropped from the journal as well.
+// Returns how many were removed, or None if the message list couldn't be touched right now
+fn delete_messages(should_remove: impl Fn(u16) -> bool) -> Option<usize> {
     let base = get_game_base().expect("Could not acquire game base");
     unsafe {
         //check if we're loading
         let loading_helper = *((base + 0x3d60ec8) as *mut u64);
         if loading_helper == 0 {
-            return;
+            return None;
         }
         let loaded = *((loading_helper + 0xED) as *mut u8);
         if loaded != 1 {
-            return;
+            return None;
         }
     }
 
-    log::info!("Removing message {message:?}");
-
     let base = get_game_base().expect("Could not acquire game base");
     let netman = {
         let instance = get_instance::<CSNetMan>().expect("Could not find CSNetMan static");
 
         if instance.is_none() {
             log::info!("CSNetMan does not have an instance");
-            return;
+            return None;
         }
 
         instance.unwrap()
@@ -53,6 +144,7 @@ pub fn delete_message(message: &str) {
         unsafe { std::mem::transmute::<usize, extern "C" fn(u64, u64)>(base + 0xe1d990) };
 
     //remove the entry(s) from the BloodMessageInsMan list
+    let mut removed = 0;
     unsafe {
         let mut current_ptr = netman
             .blood_message_db
@@ -62,8 +154,7 @@ pub fn delete_message(message: &str) {
         while !current_ptr.is_null() {
             let current = &mut *current_ptr;
 
-            let current_txt = get_message(current.template);
-            if current_txt.is_some() && normalized_is_equal(current_txt.unwrap(), message) {
+            if should_remove(current.template) {
                 // Remove the current entry
                 if !prev_ptr.is_null() {
                     (*prev_ptr).next = current.next;
@@ -78,11 +169,13 @@ pub fn delete_message(message: &str) {
 
                 log::info!("Removing {current_ptr:?}");
                 remove_message(current.template); //remove the template entry
-                                                  // Free and destruct the BloodMessageIns object
+                forget_saved_message(current.template);
+                // Free and destruct the BloodMessageIns object
                 destruct_fn(current_ptr as u64, 0); //this cleans up the sfx but doesn't free the memory
                 dealloc_fn(0, current_ptr as u64); //this frees the memory
 
                 current_ptr = next_ptr;
+                removed += 1;
             } else {
                 // Move to the next node, keeping the current as the previous
                 prev_ptr = current_ptr;
@@ -90,10 +183,202 @@ pub fn delete_message(message: &str) {
             }
         }
     }
+
+    Some(removed)
 }
 
-// Spawns a message on the floor at the players location
-pub fn spawn_message(message: &str, msg_visual: i32) {
+// Queries always get an answer, so a client waiting on a list isn't left hanging
+fn report_list_unavailable(command: &str, reason: &str) {
+    send_message(OutgoingMessage::CommandFailed {
+        command: command.to_string(),
+        reason: reason.to_string(),
+    });
+}
+
+// Reports every mod spawned message that is still on the ground to the client
+pub fn list_messages() {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            report_list_unavailable("ListBloodMessages", "the game is loading");
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            report_list_unavailable("ListBloodMessages", "the game is loading");
+            return;
+        }
+    }
+
+    let netman = {
+        let instance = get_instance::<CSNetMan>().expect("Could not find CSNetMan static");
+
+        if instance.is_none() {
+            log::info!("CSNetMan does not have an instance");
+            report_list_unavailable("ListBloodMessages", "CSNetMan does not have an instance");
+            return;
+        }
+
+        instance.unwrap()
+    };
+
+    let table = MESSAGE_TABLE
+        .get_or_init(Default::default)
+        .read()
+        .expect("Could not acquire message table read lock");
+
+    //only report the table entries that still have a BloodMessageIns in the world
+    let mut messages = Vec::new();
+    unsafe {
+        let mut current_ptr = netman
+            .blood_message_db
+            .blood_message_ins_man_1
+            .blood_message_list_head as *const BloodMessageIns;
+        while !current_ptr.is_null() {
+            let current = &*current_ptr;
+
+            if let Some(entry) = table.get(&current.template) {
+                let saved = &entry.saved;
+                messages.push(BloodMessageInfo {
+                    id: current.template,
+                    text: saved.text.clone(),
+                    tag: saved.tag.clone(),
+                    map_id: saved.map_id,
+                    x: saved.position.0,
+                    y: saved.position.1,
+                    z: saved.position.2,
+                    angle: saved.angle,
+                    msg_visual: saved.msg_visual,
+                    spawned_at: saved.spawned_at,
+                    read_count: entry.read_count,
+                });
+            }
+
+            current_ptr = current.next as *const BloodMessageIns;
+        }
+    }
+    drop(table);
+
+    send_message(OutgoingMessage::BloodMessageList { messages });
+}
+
+// Reports every message in the world, not just ours, optionally only the ones within radius of
+// the player. Text for the game's own messages comes from its message repository. Online messages
+// keep their words in the blood message db item, which we can't read, so only their template is
+// resolved and the blank stays in the text.
+pub fn list_world_messages(radius: Option<f32>) {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            report_list_unavailable("ListWorldMessages", "the game is loading");
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            report_list_unavailable("ListWorldMessages", "the game is loading");
+            return;
+        }
+    }
+
+    let netman = {
+        let instance = get_instance::<CSNetMan>().expect("Could not find CSNetMan static");
+
+        if instance.is_none() {
+            log::info!("CSNetMan does not have an instance");
+            report_list_unavailable("ListWorldMessages", "CSNetMan does not have an instance");
+            return;
+        }
+
+        instance.unwrap()
+    };
+    let Ok(Some(world_chr_man)) = get_instance::<WorldChrMan>() else {
+        log::info!("WorldChrMan does not have an instance");
+        report_list_unavailable("ListWorldMessages", "WorldChrMan does not have an instance");
+        return;
+    };
+
+    let player_map_id = world_chr_man.main_player.map_id_1;
+    let player_coordinates = get_player_coordinates(world_chr_man);
+    let category = BLOOD_MESSAGE_CATEGORY.load(Ordering::Relaxed);
+
+    let mut instances = Vec::new();
+    unsafe {
+        let mut current_ptr = netman
+            .blood_message_db
+            .blood_message_ins_man_1
+            .blood_message_list_head as *const BloodMessageIns;
+        while !current_ptr.is_null() {
+            let current = &*current_ptr;
+
+            let position = (current.position_x, current.position_y, current.position_z);
+            //positions are relative to the map, so distances only mean something in the same one
+            let distance =
+                (current.map_id == player_map_id).then(|| distance(player_coordinates, position));
+
+            if radius.is_none_or(|radius| distance.is_some_and(|distance| distance <= radius)) {
+                instances.push((
+                    current.template,
+                    current.blood_message_db_item != 0,
+                    current.map_id,
+                    position,
+                    current.angle,
+                    distance,
+                ));
+            }
+
+            current_ptr = current.next as *const BloodMessageIns;
+        }
+    }
+
+    //the text lookups go through the game, so don't hold on to the list while doing them
+    let messages = instances
+        .into_iter()
+        .map(|(template, online, map_id, position, angle, distance)| {
+            let is_mod = has_message(template);
+            let text = if is_mod {
+                MESSAGE_TABLE
+                    .get_or_init(Default::default)
+                    .read()
+                    .expect("Could not acquire message table read lock")
+                    .get(&template)
+                    .map(|entry| entry.text.to_string_lossy())
+            } else if category != 0 {
+                lookup_game_text(category, template.into())
+            } else {
+                None
+            };
+
+            WorldMessageInfo {
+                template_id: template,
+                text,
+                map_id,
+                x: position.0,
+                y: position.1,
+                z: position.2,
+                angle,
+                distance,
+                is_mod,
+                online,
+            }
+        })
+        .collect();
+
+    send_message(OutgoingMessage::WorldMessageList { messages });
+}
+
+// Spawns a message on the floor, at the players location unless the placement says otherwise.
+// The client is told the id of the new message so it can remove exactly that one later on.
+pub fn spawn_message(
+    message: &str,
+    msg_visual: &VisualRequest,
+    tag: Option<String>,
+    ttl_seconds: Option<u64>,
+    placement: &MessagePlacement,
+) {
     let base = get_game_base().expect("Could not acquire game base");
     unsafe {
         //check if we're loading
@@ -109,6 +394,113 @@ pub fn spawn_message(message: &str, msg_visual: i32) {
 
     log::info!("Spawning message {message:?}");
 
+    let Some(msg_visual) = resolve_visual(msg_visual, "SpawnBloodMessage") else {
+        return;
+    };
+
+    let netman = {
+        let instance = get_instance::<CSNetMan>().expect("Could not find CSNetMan static");
+
+        if instance.is_none() {
+            log::info!("CSNetMan does not have an instance");
+            return;
+        }
+
+        instance.unwrap()
+    };
+
+    let world_chr_man = {
+        let instance = get_instance::<WorldChrMan>().expect("Could not find WorldChrMan static");
+
+        if instance.is_none() {
+            log::info!("WorldChrMan does not have an instance");
+            return;
+        }
+
+        instance.unwrap()
+    };
+
+    let (map_id, map_coordinates, angle) = match resolve_placement(world_chr_man, placement) {
+        Ok(placement) => placement,
+        Err(e) => {
+            log::info!("Rejecting message placement {placement:?}: {e}");
+            send_message(OutgoingMessage::CommandFailed {
+                command: "SpawnBloodMessage".to_string(),
+                reason: e.to_string(),
+            });
+            return;
+        }
+    };
+
+    let saved = SavedMessage::new(
+        message,
+        tag,
+        msg_visual,
+        ttl_seconds,
+        map_id,
+        map_coordinates,
+        angle,
+    );
+    create_message(netman, saved, "SpawnBloodMessage");
+
+    enforce_message_limit();
+}
+
+// Shapes for SpawnBloodMessageFormation. Everything is laid out around the player, relative to
+// the direction the camera is facing.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum FormationShape {
+    /// Evenly spaced ring around the player, starting straight ahead
+    Circle { radius: f32 },
+    /// Straight line ahead of the player, the first message one spacing away
+    Line { spacing: f32 },
+    /// Ring that keeps widening by growth with every message
+    Spiral { radius: f32, growth: f32 },
+    /// Rows ahead of the player, centered on the facing direction
+    Grid { columns: usize, spacing: f32 },
+}
+
+// How far around a spiral each next message goes
+const SPIRAL_STEP: f32 = std::f32::consts::FRAC_PI_4;
+
+// Spawns a message for every text, laid out in the given shape around the player. Every message
+// is turned to face the player.
+pub fn spawn_formation(
+    texts: &[String],
+    shape: &FormationShape,
+    msg_visual: &VisualRequest,
+    tag: Option<String>,
+    ttl_seconds: Option<u64>,
+) {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            return;
+        }
+    }
+
+    log::info!("Spawning formation {shape:?} of {} messages", texts.len());
+
+    let Some(msg_visual) = resolve_visual(msg_visual, "SpawnBloodMessageFormation") else {
+        return;
+    };
+
+    if let FormationShape::Grid { columns: 0, .. } = shape {
+        log::info!("Grid formation needs at least one column");
+        send_message(OutgoingMessage::CommandFailed {
+            command: "SpawnBloodMessageFormation".to_string(),
+            reason: "grid formation needs at least one column".to_string(),
+        });
+        return;
+    }
+
     let netman = {
         let instance = get_instance::<CSNetMan>().expect("Could not find CSNetMan static");
 
@@ -131,25 +523,114 @@ pub fn spawn_message(message: &str, msg_visual: i32) {
         instance.unwrap()
     };
 
+    let Some((forward, right)) = get_facing_basis() else {
+        send_message(OutgoingMessage::CommandFailed {
+            command: "SpawnBloodMessageFormation".to_string(),
+            reason: PlacementError::NoFacingDirection.to_string(),
+        });
+        return;
+    };
+
     let map_id = world_chr_man.main_player.map_id_1;
+    let player_coordinates = get_player_coordinates(world_chr_man);
+    let count = texts.len();
 
-    let ride_info = &world_chr_man.main_player.module_container.ride;
-    let player_info = &world_chr_man.main_player.module_container.physics;
-    let map_coordinates = match ride_info.is_mounted {
-        0 => &player_info.unk70_position,
-        1_u8..=u8::MAX => &ride_info.position,
+    for (i, text) in texts.iter().enumerate() {
+        //offset from the player as (ahead, to the right)
+        let (ahead, side) = match *shape {
+            FormationShape::Circle { radius } => {
+                let theta = std::f32::consts::TAU * i as f32 / count as f32;
+                (theta.cos() * radius, theta.sin() * radius)
+            }
+            FormationShape::Line { spacing } => (spacing * (i + 1) as f32, 0.0),
+            FormationShape::Spiral { radius, growth } => {
+                let theta = SPIRAL_STEP * i as f32;
+                let radius = radius + growth * i as f32;
+                (theta.cos() * radius, theta.sin() * radius)
+            }
+            FormationShape::Grid { columns, spacing } => {
+                let row = i / columns;
+                let column = i % columns;
+                let centered_column = column as f32 - (columns - 1) as f32 / 2.0;
+                (spacing * (row + 1) as f32, spacing * centered_column)
+            }
+        };
+
+        let coordinates = (
+            player_coordinates.0 + forward.0 * ahead + right.0 * side,
+            player_coordinates.1,
+            player_coordinates.2 + forward.1 * ahead + right.1 * side,
+        );
+        let angle = yaw_towards(
+            player_coordinates.0 - coordinates.0,
+            player_coordinates.2 - coordinates.2,
+        );
+
+        let saved = SavedMessage::new(
+            text,
+            tag.clone(),
+            msg_visual,
+            ttl_seconds,
+            map_id,
+            coordinates,
+            angle,
+        );
+        create_message(netman, saved, "SpawnBloodMessageFormation");
+    }
+
+    enforce_message_limit();
+}
+
+// Looks up the visual in the catalogue, telling the client if it isn't one we know is safe
+fn resolve_visual(msg_visual: &VisualRequest, command: &str) -> Option<i32> {
+    match msg_visual.resolve() {
+        Ok(msg_visual) => Some(msg_visual),
+        Err(e) => {
+            log::info!("Rejecting message visual {msg_visual:?}: {e}");
+            send_message(OutgoingMessage::CommandFailed {
+                command: command.to_string(),
+                reason: e.to_string(),
+            });
+            None
+        }
+    }
+}
+
+// Gives the message an id, journals it and puts it in the world
+fn create_message(netman: &CSNetMan, saved: SavedMessage, command: &str) {
+    let template_id = match add_message(saved.clone()) {
+        Ok(template_id) => template_id,
+        Err(e) => {
+            log::error!("Could not spawn message {:?}: {e}", saved.text);
+            send_message(OutgoingMessage::CommandFailed {
+                command: command.to_string(),
+                reason: e.to_string(),
+            });
+            return;
+        }
     };
+    record_saved_message(template_id, saved.clone());
+    place_message(netman, template_id, &saved);
+
+    send_message(OutgoingMessage::BloodMessageSpawned {
+        id: template_id,
+        text: saved.text,
+        tag: saved.tag,
+    });
+}
 
+// Creates the BloodMessageIns for a message that's already in the MESSAGE_TABLE
+fn place_message(netman: &CSNetMan, template_id: u16, saved: &SavedMessage) {
     let base = get_game_base().expect("Could not acquire game base");
 
     let params = SpawnMessageParams {
         blood_message_db_item: 0x0,
-        map_id,
-        position_x: map_coordinates.0,
-        position_y: map_coordinates.1,
-        position_z: map_coordinates.2,
-        angle: -3.13653,
-        template_id: add_message(message), //the only thing we need here is a unique id for lookup later in our BLOOD_MESSAGE_LOOKUP_HOOK
+        map_id: saved.map_id,
+        position_x: saved.position.0,
+        position_y: saved.position.1,
+        position_z: saved.position.2,
+        angle: saved.angle,
+        template_id, //the only thing we need here is a unique id for lookup later in our BLOOD_MESSAGE_LOOKUP_HOOK
         unk1e: -1,
         unk1f: 66,
         unk20: 30001,
@@ -158,7 +639,7 @@ pub fn spawn_message(message: &str, msg_visual: i32) {
         unk2c: -1,
         magic_value: u32::MAX, //this is a functional magic value, don't touch
         unk34: -1,
-        message_sign_visual: msg_visual,
+        message_sign_visual: saved.msg_visual,
         unk3c: 0,
         unk40: -1,
         unk44: -1,
@@ -181,7 +662,389 @@ pub fn spawn_message(message: &str, msg_visual: i32) {
         &0u64,
     );
 
-    log::info!("Spawned message at {map_id:?} - {map_coordinates:?} template num {0} with text \"{message}\"", params.template_id);
+    log::info!(
+        "Spawned message at {:?} - {:?} angle {} template num {template_id} with text \"{}\"",
+        saved.map_id,
+        saved.position,
+        saved.angle,
+        saved.text
+    );
+}
+
+// How long a freshly placed message gets to show up in the BloodMessageInsMan list before we
+// consider it gone
+const PLACEMENT_GRACE_SECONDS: u64 = 5;
+
+// How many times a journaled message gets placed without the game ever creating it, before we
+// stop trying until the player moves to another map
+const MAX_PLACEMENT_ATTEMPTS: u32 = 3;
+
+// Failed placements per message, along with the player map they failed in
+static FAILED_PLACEMENTS: LazyLock<Mutex<HashMap<u16, (MapId, u32)>>> =
+    LazyLock::new(Default::default);
+
+// The open world areas, which are a grid of blocks where the ones next to the player are loaded
+// as well. Everything else only counts when the player is in it.
+const OPEN_WORLD_AREAS: [u8; 2] = [60, 61];
+
+fn is_map_loaded(map_id: MapId, player_map_id: MapId) -> bool {
+    if map_id == player_map_id {
+        return true;
+    }
+
+    OPEN_WORLD_AREAS.contains(&map_id.area)
+        && map_id.area == player_map_id.area
+        && map_id.index == player_map_id.index
+        && map_id.block.abs_diff(player_map_id.block) <= 1
+        && map_id.region.abs_diff(player_map_id.region) <= 1
+}
+
+// Task that keeps the MESSAGE_TABLE in line with the world. Entries whose BloodMessageIns went
+// away (map unloaded, game reloaded) are dropped, and journaled messages for the loaded map blocks
+// are respawned.
+pub fn restore_messages() {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            return;
+        }
+    }
+
+    let Ok(Some(netman)) = get_instance::<CSNetMan>() else {
+        return;
+    };
+    let Ok(Some(world_chr_man)) = get_instance::<WorldChrMan>() else {
+        return;
+    };
+
+    let mut live = HashSet::new();
+    unsafe {
+        let mut current_ptr = netman
+            .blood_message_db
+            .blood_message_ins_man_1
+            .blood_message_list_head as *const BloodMessageIns;
+        while !current_ptr.is_null() {
+            let current = &*current_ptr;
+            live.insert(current.template);
+            current_ptr = current.next as *const BloodMessageIns;
+        }
+    }
+
+    if BLOOD_MESSAGE_CATEGORY.load(Ordering::Relaxed) == 0 {
+        *WORLD_TEMPLATES.write().unwrap() = live
+            .iter()
+            .copied()
+            .filter(|template| !MESSAGE_ID_RANGE.contains(template))
+            .collect();
+    }
+
+    let now = unix_time();
+    let player_map_id = world_chr_man.main_player.map_id_1;
+    let mut failed_placements = FAILED_PLACEMENTS.lock().unwrap();
+    let mut table = MESSAGE_TABLE
+        .get_or_init(Default::default)
+        .write()
+        .expect("Could not acquire message table write lock");
+
+    table.retain(|id, entry| {
+        if live.contains(id) {
+            entry.seen_live = true;
+            failed_placements.remove(id);
+            return true;
+        }
+        if now < entry.placed_at + PLACEMENT_GRACE_SECONDS {
+            return true;
+        }
+
+        if entry.seen_live {
+            log::info!("Message {id} is no longer in the world");
+        } else {
+            //the game never made it, count it so we don't respawn it every few seconds forever
+            let failed = failed_placements.entry(*id).or_insert((player_map_id, 0));
+            if failed.0 != player_map_id {
+                *failed = (player_map_id, 0);
+            }
+            failed.1 += 1;
+            log::info!(
+                "Message {id} never showed up in the world, attempt {}",
+                failed.1
+            );
+        }
+        false
+    });
+
+    let to_restore = MESSAGE_JOURNAL
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(id, saved)| is_map_loaded(saved.map_id, player_map_id) && !table.contains_key(id))
+        .filter(|(id, _)| {
+            !failed_placements.get(id).is_some_and(|(map_id, attempts)| {
+                *map_id == player_map_id && *attempts >= MAX_PLACEMENT_ATTEMPTS
+            })
+        })
+        .map(|(id, saved)| (*id, saved.clone()))
+        .collect::<Vec<_>>();
+    drop(failed_placements);
+
+    //the journal is a file anyone can edit, so its visuals go through the catalogue like a client's.
+    //Ones it doesn't take, e.g. raw ids from a session that allowed them, get the standard sign.
+    let to_restore = to_restore
+        .into_iter()
+        .map(|(id, mut saved)| {
+            if let Err(e) = VisualRequest::Id(saved.msg_visual).resolve() {
+                log::info!("Restoring message {id} with the standard visual: {e}");
+                saved.msg_visual = STANDARD_VISUAL;
+            }
+            (id, saved)
+        })
+        .collect::<Vec<_>>();
+
+    for (id, saved) in &to_restore {
+        table.insert(*id, MessageEntry::new(saved.clone()));
+    }
+    //the game may look the text up while placing, which needs the table lock
+    drop(table);
+
+    for (id, saved) in to_restore {
+        log::info!("Restoring message {id} from the journal");
+        place_message(netman, id, &saved);
+    }
+}
+
+// Maximum amount of mod messages on the ground at once, 0 means no limit
+static MAX_LIVE_MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+// Sets the cap on live messages, evicting the oldest ones if we're already over it
+pub fn set_message_limit(max: usize) {
+    log::info!("Setting live message limit to {max}");
+
+    MAX_LIVE_MESSAGES.store(max, Ordering::Relaxed);
+    enforce_message_limit();
+}
+
+// Removes the oldest messages until we're back under MAX_LIVE_MESSAGES
+fn enforce_message_limit() {
+    let max = MAX_LIVE_MESSAGES.load(Ordering::Relaxed);
+    if max == 0 {
+        return;
+    }
+
+    let evicted = {
+        let table = MESSAGE_TABLE
+            .get_or_init(Default::default)
+            .read()
+            .expect("Could not acquire message table read lock");
+
+        if table.len() <= max {
+            return;
+        }
+
+        let mut by_age: Vec<(&u16, &MessageEntry)> = table.iter().collect();
+        //sequence restarts for messages restored from the journal, so it only breaks ties
+        by_age.sort_by_key(|(_, entry)| (entry.saved.spawned_at, entry.sequence));
+        by_age
+            .into_iter()
+            .take(table.len() - max)
+            .map(|(id, entry)| (*id, entry.saved.text.clone()))
+            .collect::<Vec<_>>()
+    };
+
+    expire(evicted, ExpiryReason::Evicted);
+}
+
+// Task that removes messages once their time to live has run out
+pub fn expire_messages() {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            return;
+        }
+    }
+
+    //go by the journal so messages in maps that aren't loaded expire as well
+    let now = unix_time();
+    let expired = MESSAGE_JOURNAL
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, saved)| saved.expires_at.is_some_and(|expires_at| expires_at <= now))
+        .map(|(id, saved)| (*id, saved.text.clone()))
+        .collect::<Vec<_>>();
+
+    expire(expired, ExpiryReason::Ttl);
+}
+
+// Despawns the given messages and lets the client know they're gone
+fn expire(messages: Vec<(u16, String)>, reason: ExpiryReason) {
+    if messages.is_empty() {
+        return;
+    }
+
+    log::info!("Expiring messages {messages:?} ({reason:?})");
+
+    if delete_messages(|template| messages.iter().any(|(id, _)| *id == template)).is_none() {
+        return;
+    }
+
+    for (id, text) in messages {
+        //the BloodMessageIns might already be gone, make sure the entry doesn't linger either way
+        remove_message(id);
+        forget_saved_message(id);
+
+        send_message(OutgoingMessage::BloodMessageExpired { id, text, reason });
+    }
+}
+
+// The angle messages have always been spawned with
+const DEFAULT_MESSAGE_ANGLE: f32 = -3.13653;
+
+/// Where to put a spawned message. Every field is optional, leaving all of them
+/// out spawns the message under the player (or Torrent) like before.
+#[derive(Debug, Default, Deserialize)]
+pub struct MessagePlacement {
+    /// Map the coordinates are relative to. Defaults to the players current map.
+    pub map_id: Option<MapId>,
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    pub z: Option<f32>,
+    /// Yaw of the message in radians.
+    pub angle: Option<f32>,
+    /// Places the message this far in front of the player (along the camera's
+    /// facing direction), turned to face the player.
+    pub distance: Option<f32>,
+}
+
+// Map, coordinates and angle of a message
+type ResolvedPlacement = (MapId, (f32, f32, f32), f32);
+
+/// Works out the map, coordinates and angle for a message. Returns why if the
+/// placement doesn't make sense.
+fn resolve_placement(
+    world_chr_man: &WorldChrMan,
+    placement: &MessagePlacement,
+) -> Result<ResolvedPlacement, PlacementError> {
+    let player_map_id = world_chr_man.main_player.map_id_1;
+    let player_coordinates = get_player_coordinates(world_chr_man);
+
+    let explicit_coordinates = match (placement.x, placement.y, placement.z) {
+        (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+        (None, None, None) => None,
+        _ => return Err(PlacementError::PartialCoordinates),
+    };
+
+    match (explicit_coordinates, placement.distance) {
+        (Some(coordinates), None) => Ok((
+            placement.map_id.unwrap_or(player_map_id),
+            coordinates,
+            placement.angle.unwrap_or(DEFAULT_MESSAGE_ANGLE),
+        )),
+        (None, Some(distance)) => {
+            if placement.map_id.is_some() {
+                return Err(PlacementError::MapIdWithDistance);
+            }
+
+            let (forward_x, forward_z) =
+                get_facing_direction().ok_or(PlacementError::NoFacingDirection)?;
+            let coordinates = (
+                player_coordinates.0 + forward_x * distance,
+                player_coordinates.1,
+                player_coordinates.2 + forward_z * distance,
+            );
+
+            //turn the message back around towards the player
+            let angle = placement
+                .angle
+                .unwrap_or(yaw_towards(-forward_x, -forward_z));
+
+            Ok((player_map_id, coordinates, angle))
+        }
+        (None, None) => {
+            if placement.map_id.is_some() {
+                return Err(PlacementError::MapIdWithoutCoordinates);
+            }
+
+            Ok((
+                player_map_id,
+                player_coordinates,
+                placement.angle.unwrap_or(DEFAULT_MESSAGE_ANGLE),
+            ))
+        }
+        (Some(_), Some(_)) => Err(PlacementError::CoordinatesWithDistance),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PlacementError {
+    #[error("placement needs all of x, y and z")]
+    PartialCoordinates,
+    #[error("placement can't combine map_id with distance")]
+    MapIdWithDistance,
+    #[error("placement has a map_id but no coordinates")]
+    MapIdWithoutCoordinates,
+    #[error("placement can't combine coordinates with distance")]
+    CoordinatesWithDistance,
+    #[error(
+        "the camera is looking straight up or down, or rolled, so there's no facing direction"
+    )]
+    NoFacingDirection,
+}
+
+/// Position of the player, or Torrent if they're riding, relative to the players map.
+fn get_player_coordinates(world_chr_man: &WorldChrMan) -> (f32, f32, f32) {
+    let ride_info = &world_chr_man.main_player.module_container.ride;
+    let player_info = &world_chr_man.main_player.module_container.physics;
+    let map_coordinates = match ride_info.is_mounted {
+        0 => &player_info.unk70_position,
+        1_u8..=u8::MAX => &ride_info.position,
+    };
+
+    (map_coordinates.0, map_coordinates.1, map_coordinates.2)
+}
+
+/// The direction the camera is looking, flattened onto the ground plane and normalized.
+fn get_facing_direction() -> Option<(f32, f32)> {
+    get_facing_basis().map(|(forward, _)| forward)
+}
+
+/// The camera's forward and right vectors, flattened onto the ground plane and normalized.
+fn get_facing_basis() -> Option<((f32, f32), (f32, f32))> {
+    let cam = get_camera()?;
+
+    //third row of the camera matrix is the forward vector, y is up
+    let length = (cam.c1 * cam.c1 + cam.c3 * cam.c3).sqrt();
+    if length < f32::EPSILON {
+        log::info!("Camera is looking straight up or down, can't work out a facing direction");
+        return None;
+    }
+    let forward = (cam.c1 / length, cam.c3 / length);
+
+    //first row is the right vector, it stays level unless the camera rolls
+    let length = (cam.a1 * cam.a1 + cam.a3 * cam.a3).sqrt();
+    if length < f32::EPSILON {
+        log::info!("Camera is rolled on its side, can't work out a facing direction");
+        return None;
+    }
+    let right = (cam.a1 / length, cam.a3 / length);
+
+    Some((forward, right))
+}
+
+/// Yaw (rotation around the y axis) that points along the given direction.
+fn yaw_towards(x: f32, z: f32) -> f32 {
+    x.atan2(z)
 }
 
 #[repr(C)]
@@ -215,14 +1078,22 @@ struct BloodMessageInsMan {
     blood_message_list_head: u64,
 }
 
+// The spawn params are copied in at 0x10, only the fields up to the template id are mapped
 #[repr(C)]
 struct BloodMessageIns {
-    unk1: [u8; 0x2C],
+    unk1: [u8; 0x10],
+    blood_message_db_item: usize,
+    map_id: MapId,
+    position_x: f32,
+    position_y: f32,
+    position_z: f32,
+    angle: f32,
     template: u16,
     unk2: [u8; 0x89A],
     next: u64,
 }
 const _: () = assert!(std::mem::size_of::<BloodMessageIns>() == 0x8d0);
+const _: () = assert!(std::mem::offset_of!(BloodMessageIns, map_id) == 0x18);
 const _: () = assert!(std::mem::offset_of!(BloodMessageIns, template) == 0x2C);
 const _: () = assert!(std::mem::offset_of!(BloodMessageIns, next) == 0x8c8);
 
@@ -253,36 +1124,279 @@ struct SpawnMessageParams {
     pub unk4c: i32,
 }
 
-static MESSAGE_COUNTER: AtomicU16 = AtomicU16::new(1);
-static MESSAGE_TABLE: OnceLock<RwLock<HashMap<u16, U16CString>>> = OnceLock::new();
+// Everything needed to spawn a message again, this is what goes into the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedMessage {
+    text: String,
+    // Optional client supplied label, used to remove a group of messages at once
+    tag: Option<String>,
+    // Where and how the message was spawned, the BloodMessageIns doesn't tell us this
+    map_id: MapId,
+    position: (f32, f32, f32),
+    angle: f32,
+    msg_visual: i32,
+    // Unix timestamps in seconds
+    spawned_at: u64,
+    expires_at: Option<u64>,
+}
 
-fn add_message(message: &str) -> u16 {
-    let index = MESSAGE_COUNTER.fetch_add(1, Ordering::Relaxed);
+impl SavedMessage {
+    fn new(
+        text: &str,
+        tag: Option<String>,
+        msg_visual: i32,
+        ttl_seconds: Option<u64>,
+        map_id: MapId,
+        position: (f32, f32, f32),
+        angle: f32,
+    ) -> Self {
+        let spawned_at = unix_time();
+        Self {
+            text: text.to_string(),
+            tag,
+            map_id,
+            position,
+            angle,
+            msg_visual,
+            spawned_at,
+            expires_at: ttl_seconds.map(|ttl| spawned_at + ttl),
+        }
+    }
+}
 
-    MESSAGE_TABLE
+struct MessageEntry {
+    // What we hand the game, saved.text with the placeholders filled in
+    text: U16CString,
+    // The game might still be holding on to the last text when it gets re-rendered, keep it alive
+    previous_text: U16CString,
+    saved: SavedMessage,
+    // When the current BloodMessageIns was created, in unix seconds
+    placed_at: u64,
+    // Order this entry was made in, breaks ties between messages spawned in the same second
+    sequence: u64,
+    read_count: u32,
+    // Read tracking, see register_read
+    last_lookup: Option<Instant>,
+    last_read: Option<Instant>,
+    in_read_range: bool,
+    // Whether the game has created a BloodMessageIns for it since it was placed
+    seen_live: bool,
+}
+
+impl MessageEntry {
+    fn new(saved: SavedMessage) -> Self {
+        let text = messagetext::render(&saved.text);
+
+        Self {
+            //client text can hold a NUL, which the game would stop at anyway
+            text: U16CString::from_str_truncate(text),
+            previous_text: U16CString::new(),
+            saved,
+            placed_at: unix_time(),
+            sequence: SPAWN_SEQUENCE.fetch_add(1, Ordering::Relaxed),
+            read_count: 0,
+            last_lookup: None,
+            last_read: None,
+            in_read_range: false,
+            seen_live: false,
+        }
+    }
+}
+
+// Template ids we hand out to our messages. GR_MenuText (see textures/) has nothing between 20201
+// and 30099. The other FMGs aren't in the repo, so the lookup hook checks the game first and only
+// answers ids it has no entry for, whatever category is asking.
+const MESSAGE_ID_RANGE: RangeInclusive<u16> = 20300..=29999;
+
+#[derive(Debug, Error)]
+pub enum MessageIdError {
+    #[error("all {0} blood message ids are in use")]
+    Exhausted(usize),
+}
+
+// Where the next search for a free id starts. Going round the range instead of always taking the
+// lowest free id means a removed message's id isn't handed straight back out.
+static NEXT_MESSAGE_ID: Mutex<u16> = Mutex::new(*MESSAGE_ID_RANGE.start());
+static SPAWN_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+static MESSAGE_TABLE: OnceLock<RwLock<HashMap<u16, MessageEntry>>> = OnceLock::new();
+
+fn add_message(saved: SavedMessage) -> Result<u16, MessageIdError> {
+    let mut table = MESSAGE_TABLE
         .get_or_init(Default::default)
         .write()
-        .expect("Could not acquire message table write lock")
-        .insert(index, U16CString::from_str(message).unwrap());
+        .expect("Could not acquire message table write lock");
+    //journaled messages keep their id across restarts, don't hand those out again
+    let journal = MESSAGE_JOURNAL.lock().unwrap();
+    let mut next_id = NEXT_MESSAGE_ID.lock().unwrap();
+
+    let index = MESSAGE_ID_RANGE
+        .skip_while(|id| id < &*next_id)
+        .chain(MESSAGE_ID_RANGE.take_while(|id| id < &*next_id))
+        .find(|id| !table.contains_key(id) && !journal.contains_key(id))
+        .ok_or(MessageIdError::Exhausted(MESSAGE_ID_RANGE.len()))?;
+
+    *next_id = if index == *MESSAGE_ID_RANGE.end() {
+        *MESSAGE_ID_RANGE.start()
+    } else {
+        index + 1
+    };
+
+    table.insert(index, MessageEntry::new(saved));
 
-    index
+    Ok(index)
 }
 
-fn normalized_is_equal(msg1: *const u16, msg2: &str) -> bool {
+fn normalized_is_equal(msg1: &str, msg2: &str) -> bool {
     let mut basic_message = String::from(msg2);
     basic_message.retain(|c| !c.is_whitespace());
-    let mut basic_text = unsafe { U16CString::from_ptr_str(msg1) }.to_string_lossy();
+    let mut basic_text = String::from(msg1);
     basic_text.retain(|c| !c.is_whitespace());
     return basic_message == basic_text;
 }
 
+fn has_message(index: u16) -> bool {
+    MESSAGE_TABLE
+        .get_or_init(Default::default)
+        .read()
+        .expect("Could not acquire message table read lock")
+        .contains_key(&index)
+}
+
+// Gets the text to show for a message, re-rendering it first if it has placeholders
 fn get_message(index: u16) -> Option<*const u16> {
+    let saved = {
+        let table = MESSAGE_TABLE
+            .get_or_init(Default::default)
+            .read()
+            .expect("Could not acquire message table read lock");
+        let entry = table.get(&index)?;
+
+        if !messagetext::is_dynamic(&entry.saved.text) {
+            return Some(entry.text.as_ptr());
+        }
+        entry.saved.clone()
+    };
+
+    let rendered = U16CString::from_str_truncate(messagetext::render(&saved.text));
+
+    let mut table = MESSAGE_TABLE
+        .get_or_init(Default::default)
+        .write()
+        .expect("Could not acquire message table write lock");
+    let entry = table.get_mut(&index)?;
+    if entry.text != rendered {
+        entry.previous_text = std::mem::replace(&mut entry.text, rendered);
+    }
+
+    Some(entry.text.as_ptr())
+}
+
+// Compares against the text the message was spawned with, ignoring whitespace
+fn message_text_matches(index: u16, message: &str) -> bool {
+    MESSAGE_TABLE
+        .get_or_init(Default::default)
+        .read()
+        .expect("Could not acquire message table read lock")
+        .get(&index)
+        .is_some_and(|f| normalized_is_equal(&f.saved.text, message))
+}
+
+fn message_has_tag(index: u16, tag: &str) -> bool {
     MESSAGE_TABLE
         .get_or_init(Default::default)
         .read()
         .expect("Could not acquire message table read lock")
         .get(&index)
-        .map(|f| f.as_ptr())
+        .is_some_and(|f| f.saved.tag.as_deref() == Some(tag))
+}
+
+// Minimum time between two read events for the same message
+const READ_DEBOUNCE: Duration = Duration::from_secs(5);
+
+// Counts a read of the message and tells the client, unless it was already read very recently
+fn register_read(index: u16, trigger: ReadTrigger) {
+    let event = {
+        let mut table = MESSAGE_TABLE
+            .get_or_init(Default::default)
+            .write()
+            .expect("Could not acquire message table write lock");
+        let Some(entry) = table.get_mut(&index) else {
+            return;
+        };
+
+        let now = Instant::now();
+        if entry
+            .last_read
+            .is_some_and(|last_read| now - last_read < READ_DEBOUNCE)
+        {
+            return;
+        }
+        entry.last_read = Some(now);
+        entry.read_count += 1;
+
+        OutgoingMessage::BloodMessageEvent {
+            id: index,
+            text: entry.saved.text.clone(),
+            map_id: entry.saved.map_id,
+            x: entry.saved.position.0,
+            y: entry.saved.position.1,
+            z: entry.saved.position.2,
+            read_count: entry.read_count,
+            trigger,
+        }
+    };
+
+    send_message(event);
+}
+
+// How close the player has to get to a message for it to count as read
+const READ_RADIUS: f32 = 2.0;
+
+// Task that counts a message as read when the player walks up to it
+pub fn detect_message_reads() {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            return;
+        }
+    }
+
+    let Ok(Some(world_chr_man)) = get_instance::<WorldChrMan>() else {
+        return;
+    };
+
+    let player_map_id = world_chr_man.main_player.map_id_1;
+    let player_coordinates = get_player_coordinates(world_chr_man);
+
+    //only the moment the player steps into range counts, standing next to a message doesn't
+    let entered = {
+        let mut table = MESSAGE_TABLE
+            .get_or_init(Default::default)
+            .write()
+            .expect("Could not acquire message table write lock");
+
+        table
+            .iter_mut()
+            .filter_map(|(id, entry)| {
+                let was_in_range = entry.in_read_range;
+                //positions in neighbouring blocks don't compare, see ReadTrigger::Proximity
+                entry.in_read_range = entry.saved.map_id == player_map_id
+                    && distance(entry.saved.position, player_coordinates) <= READ_RADIUS;
+
+                (entry.in_read_range && !was_in_range).then_some(*id)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for id in entered {
+        register_read(id, ReadTrigger::Proximity);
+    }
 }
 
 fn remove_message(id: u16) {
@@ -294,6 +1408,113 @@ fn remove_message(id: u16) {
     map.remove(&id);
 }
 
+const JOURNAL_PATH: &str = "bloodmessage-journal.json";
+
+// Every message we've spawned and not removed yet, keyed by template id. Kept on disk so the
+// messages survive the game restarting.
+static MESSAGE_JOURNAL: LazyLock<Mutex<BTreeMap<u16, SavedMessage>>> =
+    LazyLock::new(|| Mutex::new(load_journal()));
+
+fn load_journal() -> BTreeMap<u16, SavedMessage> {
+    let Ok(content) = fs::read_to_string(JOURNAL_PATH) else {
+        log::info!("No message journal found at {JOURNAL_PATH}");
+        return BTreeMap::new();
+    };
+
+    match serde_json::from_str::<BTreeMap<u16, SavedMessage>>(&content) {
+        Ok(mut journal) => {
+            //messages journaled before the id range moved get new ids inside it
+            let mut moved = Vec::new();
+            journal.retain(|id, saved| {
+                let in_range = MESSAGE_ID_RANGE.contains(id);
+                if !in_range {
+                    moved.push((*id, saved.clone()));
+                }
+                in_range
+            });
+
+            let free_ids = MESSAGE_ID_RANGE
+                .filter(|id| !journal.contains_key(id))
+                .take(moved.len())
+                .collect::<Vec<_>>();
+            for (i, (old_id, saved)) in moved.into_iter().enumerate() {
+                match free_ids.get(i) {
+                    Some(id) => {
+                        log::info!("Moving journaled message {old_id} to id {id}");
+                        journal.insert(*id, saved);
+                        mark_journal_dirty();
+                    }
+                    None => log::info!("No free id left for journaled message {old_id} {saved:?}"),
+                }
+            }
+
+            log::info!("Loaded {} messages from the journal", journal.len());
+            journal
+        }
+        Err(e) => {
+            //keep the broken journal around, the next write would otherwise replace it
+            let backup_path = format!("{JOURNAL_PATH}.bad");
+            log::error!("Could not read message journal, moving it to {backup_path}: {e:?}");
+            if let Err(e) = fs::rename(JOURNAL_PATH, &backup_path) {
+                log::error!("Could not move the broken message journal: {e:?}");
+            }
+            BTreeMap::new()
+        }
+    }
+}
+
+// Set when the journal changed since it was last written. Writes are batched by flush_journal
+// instead of happening on the game thread for every change.
+static JOURNAL_DIRTY: AtomicBool = AtomicBool::new(false);
+
+fn mark_journal_dirty() {
+    JOURNAL_DIRTY.store(true, Ordering::Relaxed);
+}
+
+// Writes the journal to disk if it changed. The file is written next to the journal and then
+// moved over it, so a crash halfway through never leaves a broken journal behind.
+pub fn flush_journal() {
+    if !JOURNAL_DIRTY.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    let content = serde_json::to_string_pretty(&*MESSAGE_JOURNAL.lock().unwrap()).unwrap();
+    let temp_path = format!("{JOURNAL_PATH}.tmp");
+    if let Err(e) =
+        fs::write(&temp_path, content).and_then(|_| fs::rename(&temp_path, JOURNAL_PATH))
+    {
+        log::error!("Could not write message journal: {e:?}");
+        mark_journal_dirty();
+    }
+}
+
+fn record_saved_message(id: u16, saved: SavedMessage) {
+    let mut journal = MESSAGE_JOURNAL.lock().unwrap();
+    journal.insert(id, saved);
+    mark_journal_dirty();
+}
+
+fn forget_saved_message(id: u16) {
+    let mut journal = MESSAGE_JOURNAL.lock().unwrap();
+    if journal.remove(&id).is_some() {
+        mark_journal_dirty();
+    }
+}
+
+// Drops every journaled message that matches, loaded or not. Returns how many went.
+fn forget_saved_messages(should_forget: impl Fn(u16, &SavedMessage) -> bool) -> usize {
+    let mut journal = MESSAGE_JOURNAL.lock().unwrap();
+    let before = journal.len();
+    journal.retain(|id, saved| !should_forget(*id, saved));
+
+    let forgotten = before - journal.len();
+    if forgotten > 0 {
+        log::info!("Forgot {forgotten} journaled messages");
+        mark_journal_dirty();
+    }
+    forgotten
+}
+
 static_detour! {
     static BLOOD_MESSAGE_LOOKUP_HOOK: unsafe extern "system" fn(u64, u32) -> *const u16;
 }
@@ -314,37 +1535,91 @@ pub fn init_hooks() {
     }
 }
 
-lazy_static! {
-    static ref msg_last_read: AtomicU64 = AtomicU64::new(0);
-}
+// Lookups come in every frame while a message is being read. A pause this long in between
+// lookups means the next one is somebody reading it again.
+const LOOKUP_PAUSE: Duration = Duration::from_millis(500);
 
 fn blood_message_lookup(param_1: u64, template_id: u32) -> *const u16 {
-    if let Ok(message_index) = u16::try_from(template_id) {
-        if let Some(message) = get_message(message_index) {
-            //i can't just call SEND here, this is hit every frame. Check the last time we read it
-            let now = SystemTime::now();
-            let cur_read_time = now
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs();
-            if cur_read_time - msg_last_read.load(Ordering::Relaxed) > 2 {
-                if let Some(sender) = GAMEPUSH_SEND.lock().unwrap().as_ref() {
-                    sender
-                        .send(Message::Text(
-                            serde_json::to_string(&OutgoingMessage::BloodMessageEvent {
-                                text: unsafe { U16CStr::from_ptr_str(message) }
-                                    .to_string()
-                                    .unwrap(),
-                            })
-                            .unwrap(),
-                        ))
-                        .expect("Send failed");
-                }
-            }
-            msg_last_read.store(cur_read_time, Ordering::Relaxed);
-            return message;
+    let entry = unsafe { BLOOD_MESSAGE_LOOKUP_HOOK.call(param_1, template_id) };
+
+    let Some(message_index) = u16::try_from(template_id)
+        .ok()
+        .filter(|id| MESSAGE_ID_RANGE.contains(id))
+    else {
+        detect_blood_message_category(param_1, template_id, entry);
+        return entry;
+    };
+
+    //the game's own entries always win, so our ids can never hide its text in any FMG
+    if !entry.is_null() {
+        return entry;
+    }
+
+    //once we know the blood message category, lookups from anywhere else aren't ours
+    let category = BLOOD_MESSAGE_CATEGORY.load(Ordering::Relaxed);
+    if category != 0 && category != param_1 {
+        return entry;
+    }
+
+    if let Some(message) = get_message(message_index) {
+        //i can't just call SEND here, this is hit every frame. Only count the first lookup after a pause
+        let now = Instant::now();
+        let is_new_read = MESSAGE_TABLE
+            .get_or_init(Default::default)
+            .write()
+            .expect("Could not acquire message table write lock")
+            .get_mut(&message_index)
+            .and_then(|entry| entry.last_lookup.replace(now))
+            .is_none_or(|last_lookup| now - last_lookup > LOOKUP_PAUSE);
+
+        if is_new_read {
+            register_read(message_index, ReadTrigger::Interaction);
         }
+        return message;
+    }
+
+    entry
+}
+
+// The MsgRepositoryImpCategory the game looks blood message templates up in, 0 until it's known.
+// We don't know where it lives, so it's taken from the game rendering a message that's already in
+// the world, see detect_blood_message_category.
+static BLOOD_MESSAGE_CATEGORY: AtomicU64 = AtomicU64::new(0);
+
+// Templates of the game's own messages in the world, kept by restore_messages until the category
+// is known
+static WORLD_TEMPLATES: LazyLock<RwLock<HashSet<u16>>> = LazyLock::new(Default::default);
+
+// A lookup of a template that a message in the world uses, which comes back with a template blank
+// in it, can only be the game rendering that message. Its category is the blood message one.
+fn detect_blood_message_category(category: u64, template_id: u32, entry: *const u16) {
+    if entry.is_null() || BLOOD_MESSAGE_CATEGORY.load(Ordering::Relaxed) != 0 {
+        return;
+    }
+
+    let is_world_template = u16::try_from(template_id)
+        .is_ok_and(|template| WORLD_TEMPLATES.read().unwrap().contains(&template));
+    if !is_world_template {
+        return;
+    }
+
+    let text = unsafe { U16CStr::from_ptr_str(entry) }.to_string_lossy();
+    if text.contains(TEMPLATE_BLANK) {
+        log::info!("Blood message templates are in category {category:#x}");
+        BLOOD_MESSAGE_CATEGORY.store(category, Ordering::Relaxed);
+        WORLD_TEMPLATES.write().unwrap().clear();
+    }
+}
+
+// How the game's templates mark where the word goes
+const TEMPLATE_BLANK: &str = "****";
+
+// Looks an entry up with the games own GetEntry, skipping our hook
+fn lookup_game_text(category: u64, id: u32) -> Option<String> {
+    let text = unsafe { BLOOD_MESSAGE_LOOKUP_HOOK.call(category, id) };
+    if text.is_null() {
+        return None;
     }
 
-    unsafe { BLOOD_MESSAGE_LOOKUP_HOOK.call(param_1, template_id) }
+    Some(unsafe { U16CStr::from_ptr_str(text) }.to_string_lossy())
 }
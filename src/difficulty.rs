@@ -1,6 +1,553 @@
+use crate::player::{
+    find_speffect, remove_speffect, ChrIns, GameDataMan, MapId, SpEffectParamRates, WorldChrMan,
+};
+use crate::reflection::get_instance;
 use crate::util::{
-    display_message, get_game_base, get_game_data_man, get_world_chr_man, FullscreenMsgIndex,
+    apply_speffect, display_custom_text_message, display_message, get_game_base, get_game_data_man,
+    get_world_chr_man, is_hostile, send_message, within_radius, DifficultyChangeReason,
+    DifficultyModifierInfo, DifficultySource, FullscreenMsgIndex, OutgoingMessage, ScalingRuleInfo,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+// The highest NG+ level the game scales enemies for
+const MAX_NG_LEVEL: u32 = 7;
+
+const TIERS_PATH: &str = "difficulty-tiers.json";
+
+/// A difficulty level past NG+7, defined in the tiers config. The game keeps scaling at NG+7 and
+/// the tier goes on top of that.
+///
+/// A tier only touches enemies. Hp and poise are multiplied on each one. Damage and defense come
+/// from the enemy's NG+ SpEffect, so those multipliers go into that SpEffect's param row. Any other
+/// SpEffects the tier names are applied as they are. Multipliers have to be above 0.
+#[derive(Debug, Deserialize)]
+struct DifficultyTier {
+    level: u32,
+    announcement: String,
+    #[serde(default = "unscaled")]
+    hp_multiplier: f32,
+    #[serde(default = "unscaled")]
+    damage_multiplier: f32,
+    // Divides the damage the enemy takes
+    #[serde(default = "unscaled")]
+    defense_multiplier: f32,
+    #[serde(default = "unscaled")]
+    poise_multiplier: f32,
+    #[serde(default)]
+    speffects: Vec<u32>,
+}
+
+fn unscaled() -> f32 {
+    1.0
+}
+
+// Multipliers divide and get divided by, so anything but a positive number breaks the scaling
+fn is_valid_multiplier(multiplier: f32) -> bool {
+    multiplier.is_finite() && multiplier > 0.0
+}
+
+static DIFFICULTY_TIERS: LazyLock<Vec<DifficultyTier>> = LazyLock::new(load_tiers);
+
+fn load_tiers() -> Vec<DifficultyTier> {
+    let Ok(content) = fs::read_to_string(TIERS_PATH) else {
+        log::info!("No difficulty tiers found at {TIERS_PATH}");
+        return Vec::new();
+    };
+
+    match serde_json::from_str::<Vec<DifficultyTier>>(&content) {
+        Ok(mut tiers) => {
+            tiers.retain(|tier| {
+                if tier.level <= MAX_NG_LEVEL {
+                    log::info!(
+                        "Dropping difficulty tier {tier:?}, the game already has that level"
+                    );
+                    return false;
+                }
+
+                let multipliers = [
+                    tier.hp_multiplier,
+                    tier.damage_multiplier,
+                    tier.defense_multiplier,
+                    tier.poise_multiplier,
+                ];
+                if !multipliers.into_iter().all(is_valid_multiplier) {
+                    log::error!(
+                        "Dropping difficulty tier {tier:?}, multipliers have to be above 0"
+                    );
+                    return false;
+                }
+
+                true
+            });
+            tiers.sort_by_key(|tier| tier.level);
+            tiers.dedup_by_key(|tier| tier.level);
+            log::info!("Loaded {} difficulty tiers", tiers.len());
+            tiers
+        }
+        Err(e) => {
+            log::error!("Could not read difficulty tiers: {e:?}");
+            Vec::new()
+        }
+    }
+}
+
+fn get_tier(level: u32) -> Option<&'static DifficultyTier> {
+    DIFFICULTY_TIERS.iter().find(|tier| tier.level == level)
+}
+
+/// Extra scaling for only some enemies, on top of whatever the difficulty level does. Added and
+/// removed at runtime by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingRule {
+    pub target: ScalingTarget,
+    #[serde(default = "unscaled")]
+    pub hp_multiplier: f32,
+    #[serde(default)]
+    pub speffects: Vec<u32>,
+}
+
+/// Which characters a scaling rule applies to. There's no known boss flag on a character, so
+/// bosses are targeted by their npc param ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ScalingTarget {
+    NpcParam { ids: Vec<i32> },
+    Radius { radius: f32 },
+    MapArea { area: u8 },
+}
+
+impl ScalingTarget {
+    fn matches(&self, chr_ins: &ChrIns, player: &ChrIns) -> bool {
+        match self {
+            ScalingTarget::NpcParam { ids } => ids.contains(&chr_ins.npc_id_1),
+            ScalingTarget::Radius { radius } => within_radius(chr_ins, player, *radius),
+            ScalingTarget::MapArea { area } => chr_ins.map_id_1.area == *area,
+        }
+    }
+}
+
+static SCALING_RULES: LazyLock<Mutex<BTreeMap<u32, ScalingRule>>> = LazyLock::new(Default::default);
+static NEXT_RULE_ID: AtomicU32 = AtomicU32::new(1);
+
+pub fn add_scaling_rule(rule: ScalingRule) {
+    if !is_valid_multiplier(rule.hp_multiplier) {
+        log::info!("Rejecting scaling rule {rule:?}, hp_multiplier has to be above 0");
+        send_message(OutgoingMessage::CommandFailed {
+            command: "AddScalingRule".to_string(),
+            reason: format!("hp_multiplier {} has to be above 0", rule.hp_multiplier),
+        });
+        return;
+    }
+
+    let id = NEXT_RULE_ID.fetch_add(1, Ordering::Relaxed);
+    log::info!("Adding scaling rule {id}: {rule:?}");
+
+    SCALING_RULES.lock().unwrap().insert(id, rule);
+    send_message(OutgoingMessage::ScalingRuleAdded { id });
+}
+
+// Characters a removed rule scaled go back to normal on the next scaling pass
+pub fn remove_scaling_rule(id: u32) {
+    log::info!("Removing scaling rule {id}");
+
+    match SCALING_RULES.lock().unwrap().remove(&id) {
+        Some(rule) => mark_stale_speffects(rule.speffects),
+        None => send_message(OutgoingMessage::CommandFailed {
+            command: "RemoveScalingRule".to_string(),
+            reason: format!("no scaling rule with id {id}"),
+        }),
+    }
+}
+
+pub fn list_scaling_rules() {
+    let rules = SCALING_RULES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, rule)| ScalingRuleInfo {
+            id: *id,
+            rule: rule.clone(),
+        })
+        .collect();
+
+    send_message(OutgoingMessage::ScalingRuleList { rules });
+}
+
+// The custom tier we're on, 0 when it's just the game's own clear count
+static CUSTOM_TIER: AtomicU32 = AtomicU32::new(0);
+
+pub fn get_level(game_data_man: &GameDataMan) -> u32 {
+    match CUSTOM_TIER.load(Ordering::Relaxed) {
+        0 => game_data_man.clear_count,
+        tier => tier,
+    }
+}
+
+fn is_valid_level(level: u32) -> bool {
+    level <= MAX_NG_LEVEL || get_tier(level).is_some()
+}
+
+// The next level up, the next NG+ level and then the next tier. Stays put at the top.
+fn level_above(level: u32) -> u32 {
+    if level < MAX_NG_LEVEL {
+        return level + 1;
+    }
+
+    DIFFICULTY_TIERS
+        .iter()
+        .map(|tier| tier.level)
+        .find(|tier| *tier > level)
+        .unwrap_or(level)
+}
+
+fn level_below(level: u32) -> u32 {
+    if level <= MAX_NG_LEVEL {
+        return level.saturating_sub(1);
+    }
+
+    DIFFICULTY_TIERS
+        .iter()
+        .map(|tier| tier.level)
+        .rfind(|tier| *tier < level)
+        .unwrap_or(MAX_NG_LEVEL)
+}
+
+// A difficulty level that only holds for a while
+struct TimedModifier {
+    id: u32,
+    level: u32,
+    until: Instant,
+}
+
+// Timed modifiers stack, the newest one decides the level. The base is the level to go back to
+// once they've all run out, it only exists while there are modifiers.
+struct TimedModifiers {
+    base: u32,
+    stack: Vec<TimedModifier>,
+}
+
+static TIMED_MODIFIERS: Mutex<Option<TimedModifiers>> = Mutex::new(None);
+static NEXT_MODIFIER_ID: AtomicU32 = AtomicU32::new(1);
+
+// The level that holds once every timed modifier has run out
+fn permanent_level(game_data_man: &GameDataMan) -> u32 {
+    match TIMED_MODIFIERS.lock().unwrap().as_ref() {
+        Some(timed) => timed.base,
+        None => get_level(game_data_man),
+    }
+}
+
+// Timed changes go from the level right now, changes for good from the permanent one
+fn level_to_change(game_data_man: &GameDataMan, duration_seconds: Option<u64>) -> u32 {
+    match duration_seconds {
+        Some(_) => get_level(game_data_man),
+        None => permanent_level(game_data_man),
+    }
+}
+
+// Moves to a level either for good or, with a duration, until the modifier runs out. Changes for
+// good while modifiers are running only change the level that's restored after them, the timed
+// level stays until it runs out.
+fn set_level(
+    game_data_man: &mut GameDataMan,
+    level: u32,
+    isup: bool,
+    duration_seconds: Option<u64>,
+    source: DifficultySource,
+) {
+    let mut timed = TIMED_MODIFIERS.lock().unwrap();
+    match duration_seconds {
+        Some(duration_seconds) => {
+            let id = NEXT_MODIFIER_ID.fetch_add(1, Ordering::Relaxed);
+            let base = get_level(game_data_man);
+            let timed = timed.get_or_insert_with(|| TimedModifiers {
+                base,
+                stack: Vec::new(),
+            });
+            timed.stack.push(TimedModifier {
+                id,
+                level,
+                until: Instant::now() + Duration::from_secs(duration_seconds),
+            });
+
+            send_message(OutgoingMessage::DifficultyModifierAdded {
+                id,
+                level,
+                remaining_seconds: duration_seconds,
+            });
+        }
+        None => {
+            if let Some(timed) = timed.as_mut() {
+                log::info!("Difficulty goes back to {level} once the timed modifiers run out");
+                timed.base = level;
+                return;
+            }
+        }
+    }
+    drop(timed);
+
+    let source = match duration_seconds {
+        Some(_) => DifficultySource::TimedModifier,
+        None => source,
+    };
+    change_level(game_data_man, level, isup, source);
+}
+
+// Drops the timed modifiers that ran out. When the newest one goes, the level falls back to the
+// one under it, or to the base when it was the last. One that runs out under a newer modifier
+// just leaves the stack, so overlapping modifiers always end on the right level.
+pub fn expire_modifiers() {
+    let mut timed = TIMED_MODIFIERS.lock().unwrap();
+    let Some(modifiers) = timed.as_mut() else {
+        return;
+    };
+
+    let now = Instant::now();
+    if modifiers.stack.iter().all(|modifier| modifier.until > now) {
+        return;
+    }
+
+    let Some(game_data_man) = get_game_data_man() else {
+        return;
+    };
+
+    let top = modifiers.stack.last().map(|modifier| modifier.id);
+    let mut expired = Vec::new();
+    modifiers.stack.retain(|modifier| {
+        if modifier.until > now {
+            return true;
+        }
+        expired.push((modifier.id, modifier.level));
+        false
+    });
+
+    let restored = match modifiers.stack.last() {
+        Some(modifier) if Some(modifier.id) == top => None,
+        Some(modifier) => Some(modifier.level),
+        None => Some(modifiers.base),
+    };
+    if modifiers.stack.is_empty() {
+        *timed = None;
+    }
+    drop(timed);
+
+    for (id, level) in expired {
+        log::info!("Difficulty modifier {id} at level {level} ran out");
+        send_message(OutgoingMessage::DifficultyModifierExpired {
+            id,
+            level,
+            restored,
+        });
+    }
+
+    if let Some(restored) = restored {
+        let level = get_level(game_data_man);
+        if restored != level {
+            change_level(
+                game_data_man,
+                restored,
+                restored > level,
+                DifficultySource::ModifierExpired,
+            );
+        }
+    }
+}
+
+pub fn list_modifiers() {
+    let now = Instant::now();
+    let (base, modifiers) = match TIMED_MODIFIERS.lock().unwrap().as_ref() {
+        Some(timed) => (
+            Some(timed.base),
+            timed
+                .stack
+                .iter()
+                .map(|modifier| DifficultyModifierInfo {
+                    id: modifier.id,
+                    level: modifier.level,
+                    remaining_seconds: modifier.until.saturating_duration_since(now).as_secs(),
+                })
+                .collect(),
+        ),
+        None => (None, Vec::new()),
+    };
+
+    send_message(OutgoingMessage::DifficultyModifierList { base, modifiers });
+}
+
+// Moves to a level, custom tiers leave the game at NG+7 and scale on top of it
+fn change_level(game_data_man: &mut GameDataMan, level: u32, isup: bool, source: DifficultySource) {
+    let old = get_level(game_data_man);
+
+    game_data_man.clear_count = level.min(MAX_NG_LEVEL);
+    CUSTOM_TIER.store(
+        if level > MAX_NG_LEVEL { level } else { 0 },
+        Ordering::Relaxed,
+    );
+
+    //applying a SpEffect a character already has only refreshes it, and the game doesn't reliably
+    //take scaling back off, so strip it in either direction and let set_scaling reapply what the
+    //new level needs
+    if level != old {
+        let speffects = DIFFICULTY_TIERS
+            .iter()
+            .filter(|tier| tier.level != level)
+            .flat_map(|tier| tier.speffects.iter().copied());
+        mark_stale_speffects(speffects);
+    }
+
+    announce_level(level, isup);
+
+    //dynamic difficulty counts from the last level change, whatever made it. Its own changes reset
+    //the counting themselves, and hold the lock while doing it.
+    if level != old && !matches!(source, DifficultySource::Dynamic) {
+        if let Some(state) = DYNAMIC_DIFFICULTY.lock().unwrap().as_mut() {
+            state.deaths = 0;
+            state.since = Instant::now();
+        }
+    }
+
+    if level != old {
+        send_message(OutgoingMessage::DifficultyChanged {
+            old,
+            new: level,
+            source,
+        });
+    }
+}
+
+// SpEffects to take off every loaded character on the next scaling pass. Some even when empty,
+// the NG+ scaling SpEffect always comes off.
+static STALE_SPEFFECTS: Mutex<Option<Vec<u32>>> = Mutex::new(None);
+
+fn mark_stale_speffects(speffects: impl IntoIterator<Item = u32>) {
+    STALE_SPEFFECTS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Vec::new)
+        .extend(speffects);
+}
+
+fn announce_level(level: u32, isup: bool) {
+    match get_tier(level) {
+        Some(tier) => display_custom_text_message(tier.announcement.clone()),
+        //the game has no banner for dropping down to NG+7
+        None if level == MAX_NG_LEVEL && !isup => {
+            display_custom_text_message(format!("NG+{MAX_NG_LEVEL}"))
+        }
+        None => display_message(ng_val_to_msg(level, isup)),
+    }
+}
+
+// What scaling is on a character, so it only has to be applied when something changes
+struct ScaledCharacter {
+    // The character's handle. A character that respawns keeps its handle but gets a new ChrIns
+    // without any of our scaling, and a freed ChrIns can be reused for someone else.
+    handle: (i32, MapId),
+    // Passes the character was missing from the chr set in a row
+    missed: u32,
+    // The level the NG+ and tier SpEffects went on at, None when they need to go on again
+    level: Option<u32>,
+    // Scaling rules whose SpEffects are on it
+    rules: Vec<u32>,
+    // The hp before any multiplier, and the multiplier it has now
+    hp_base: u32,
+    multiplier: f32,
+    // Same for poise
+    poise_base: f32,
+    poise_multiplier: f32,
+}
+
+impl ScaledCharacter {
+    fn new(handle: (i32, MapId), chr: &ChrIns) -> Self {
+        Self {
+            handle,
+            missed: 0,
+            level: None,
+            rules: Vec::new(),
+            hp_base: chr.module_container.data.hp_base,
+            multiplier: 1.0,
+            poise_base: chr.module_container.super_armor.sa_durability_max,
+            poise_multiplier: 1.0,
+        }
+    }
+}
+
+// Keyed by the ChrIns, which is what the scaled hp and poise live on
+static SCALED_CHARACTERS: LazyLock<Mutex<HashMap<u64, ScaledCharacter>>> =
+    LazyLock::new(Default::default);
+
+// How many passes a character can be missing before it counts as gone. The chr set only covers
+// the block the player is in, so a character can drop out for a moment while its ChrIns, with our
+// scaling on it, is still around. Taking the base again then would scale the scaled hp.
+const MISSED_PASSES_BEFORE_GONE: u32 = 10;
+
+// Brings a character's hp in line with the multiplier. The unscaled hp is kept, so changing tiers
+// doesn't stack multipliers, and the current hp moves with the max so full hp stays full.
+fn scale_hp(chr_ins: &mut ChrIns, multiplier: f32, scaled: &mut ScaledCharacter) {
+    if scaled.multiplier == multiplier {
+        return;
+    }
+
+    let data = &mut chr_ins.module_container.data;
+    let ratio = multiplier / scaled.multiplier;
+    data.hp_base = (scaled.hp_base as f32 * multiplier) as u32;
+    data.hp_max = (data.hp_max as f32 * ratio) as u32;
+    data.hp = ((data.hp as f32 * ratio) as u32).min(data.hp_max);
+    scaled.multiplier = multiplier;
+}
+
+fn scale_poise(chr_ins: &mut ChrIns, multiplier: f32, scaled: &mut ScaledCharacter) {
+    if scaled.poise_multiplier == multiplier {
+        return;
+    }
+
+    let super_armor = &mut chr_ins.module_container.super_armor;
+    let ratio = multiplier / scaled.poise_multiplier;
+    super_armor.sa_durability_max = scaled.poise_base * multiplier;
+    super_armor.sa_durability =
+        (super_armor.sa_durability * ratio).min(super_armor.sa_durability_max);
+    scaled.poise_multiplier = multiplier;
+}
+
+// A NG+ SpEffect param row we changed, with what it held before. Rows are shared by every
+// character with that SpEffect and stay loaded for the whole game, so they're scaled once per row
+// rather than per character.
+struct ScaledRow {
+    original: SpEffectParamRates,
+    damage: f32,
+    defense: f32,
+}
+
+static SCALED_ROWS: LazyLock<Mutex<HashMap<usize, ScaledRow>>> = LazyLock::new(Default::default);
+
+// Brings a row's attack and damage cut rates in line with the multipliers, starting from the
+// original values so tier changes don't stack
+unsafe fn scale_rates(row: usize, scaled: &mut ScaledRow, damage: f32, defense: f32) {
+    if scaled.damage == damage && scaled.defense == defense {
+        return;
+    }
+
+    let original = &scaled.original;
+    let row = &mut *(row as *mut SpEffectParamRates);
+    row.slash_damage_cut_rate = original.slash_damage_cut_rate / defense;
+    row.blow_damage_cut_rate = original.blow_damage_cut_rate / defense;
+    row.thrust_damage_cut_rate = original.thrust_damage_cut_rate / defense;
+    row.neutral_damage_cut_rate = original.neutral_damage_cut_rate / defense;
+    row.magic_damage_cut_rate = original.magic_damage_cut_rate / defense;
+    row.fire_damage_cut_rate = original.fire_damage_cut_rate / defense;
+    row.thunder_damage_cut_rate = original.thunder_damage_cut_rate / defense;
+    row.physics_attack_power_rate = original.physics_attack_power_rate * damage;
+    row.magic_attack_power_rate = original.magic_attack_power_rate * damage;
+    row.fire_attack_power_rate = original.fire_attack_power_rate * damage;
+    row.thunder_attack_power_rate = original.thunder_attack_power_rate * damage;
+    scaled.damage = damage;
+    scaled.defense = defense;
+}
 
 pub fn set_scaling() {
     let base = get_game_base().expect("Could not acquire game base");
@@ -16,8 +563,29 @@ pub fn set_scaling() {
         }
     }
 
-    let apply_speffect_fn =
-        unsafe { std::mem::transmute::<usize, extern "C" fn(u64, u32, u8)>(base + 0x3e8cf0) };
+    let Some(level) = get_game_data_man().map(|game_data_man| get_level(game_data_man)) else {
+        return;
+    };
+    let tier = get_tier(CUSTOM_TIER.load(Ordering::Relaxed));
+    let rules: Vec<(u32, ScalingRule)> = SCALING_RULES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, rule)| (*id, rule.clone()))
+        .collect();
+    let player = get_instance::<WorldChrMan>()
+        .ok()
+        .flatten()
+        .map(|world_chr_man| world_chr_man.main_player);
+    let mut scaled_characters = SCALED_CHARACTERS.lock().unwrap();
+
+    //rows scaled for an earlier tier follow the tier even if none of their characters are loaded
+    let damage_multiplier = tier.map_or(1.0, |tier| tier.damage_multiplier);
+    let defense_multiplier = tier.map_or(1.0, |tier| tier.defense_multiplier);
+    let mut scaled_rows = SCALED_ROWS.lock().unwrap();
+    for (row, scaled) in scaled_rows.iter_mut() {
+        unsafe { scale_rates(*row, scaled, damage_multiplier, defense_multiplier) };
+    }
 
     //Apply the NG+ speffects to all active enemies
     //This is run as a task, so it will apply to any newly loaded enemies as well
@@ -34,78 +602,196 @@ pub fn set_scaling() {
         return;
     }
 
+    //a chr set that couldn't be read says nothing about who unloaded, so nothing gets forgotten
+    let Some(characters) = (unsafe { read_characters(world_chr_man) }) else {
+        return;
+    };
+
+    //only taken once there are characters to take them off, otherwise they'd stay on
+    let stale_speffects = STALE_SPEFFECTS.lock().unwrap().take();
+    for scaled in scaled_characters.values_mut() {
+        scaled.missed += 1;
+    }
+
     unsafe {
-        //get list of all enemies around the current player
-        //This code is taken from inuNorii's Kill All Mobs script in TGA table
-        let mut chr_set = *((world_chr_man + 0x1CC60) as *mut u64); //legacy dungeon
-        if chr_set == 0 {
-            return;
-        }
-        let open_field_chr_set = *((world_chr_man + 0x1E270) as *mut u64); //open world
-        if open_field_chr_set == 0 {
-            return;
-        }
+        for chrins_enemy in characters {
+            let chrins_enemy_vtable = *((chrins_enemy + 0) as *mut usize);
 
-        let mut use_legacy = false;
-        let mut chr_count = *((open_field_chr_set + 0x20) as *mut u32);
-        if chr_count == 0xffffffff {
-            chr_count = *((chr_set + 0x10) as *mut u32);
-            use_legacy = true;
-        }
+            //for this enemy, get the speffect for NG+1 scaling speffect
+            //need to check vtable to determine offset
+            let param: u64;
+            //enemy
+            if chrins_enemy_vtable == base + 0x2a44010 {
+                param = *((chrins_enemy + 0x598) as *mut u64);
+            }
+            //player
+            else if chrins_enemy_vtable == base + 0x2a7cb40 {
+                param = *((chrins_enemy + 0x5f0) as *mut u64);
+            }
+            //unknown
+            else {
+                continue;
+            }
+            if param == 0 {
+                continue;
+            }
 
-        if use_legacy {
-            chr_set = *((chr_set + 0x18) as *mut u64);
-        } else {
-            chr_set = *((open_field_chr_set + 0x18) as *mut u64);
-        }
-        if chr_set == 0 {
-            return;
-        }
+            let npcparam_st = *((param + 0) as *mut u64);
+            if npcparam_st == 0 {
+                continue;
+            }
+            let gameclear_speffect = *((npcparam_st + 0x6c) as *mut u32);
+            if gameclear_speffect == 0 {
+                continue;
+            }
 
-        for i in 1..chr_count {
-            let chrins_enemy = *((chr_set + (i * 0x10) as u64) as *mut u64);
-            if chrins_enemy != 0 {
-                let chrins_enemy_vtable = *((chrins_enemy + 0) as *mut usize);
-                if chrins_enemy_vtable == 0 {
-                    continue;
-                }
+            let chr_ins = &mut *(chrins_enemy as *mut ChrIns);
+            //the NG+ scaling is the game's and goes on everyone, tiers and rules only make enemies
+            //tougher
+            let hostile = is_hostile(chr_ins);
+            let tier = tier.filter(|_| hostile);
+            let handle = (
+                chr_ins.field_ins_handle.instance_id,
+                chr_ins.field_ins_handle.map_id,
+            );
 
-                //for this enemy, get the speffect for NG+1 scaling speffect
-                //need to check vtable to determine offset
-                let param: u64;
-                //enemy
-                if chrins_enemy_vtable == base + 0x2a44010 {
-                    param = *((chrins_enemy + 0x598) as *mut u64);
-                }
-                //player
-                else if chrins_enemy_vtable == base + 0x2a7cb40 {
-                    param = *((chrins_enemy + 0x5f0) as *mut u64);
+            let scaled = scaled_characters
+                .entry(chrins_enemy)
+                .or_insert_with(|| ScaledCharacter::new(handle, chr_ins));
+            if scaled.handle != handle {
+                *scaled = ScaledCharacter::new(handle, chr_ins);
+            }
+            scaled.missed = 0;
+
+            //the level changed, take the scaling off. Applying it again right away would only
+            //refresh the old one, so the new level goes on next pass.
+            if let Some(stale_speffects) = &stale_speffects {
+                remove_speffect(chr_ins, gameclear_speffect);
+                for speffect in stale_speffects {
+                    remove_speffect(chr_ins, *speffect);
                 }
-                //unknown
-                else {
-                    continue;
+                scaled.level = None;
+                continue;
+            }
+
+            //the game can take SpEffects off by itself (e.g. when the character resets), so the
+            //cache only counts while they're still in its list
+            let missing = |speffect: &u32| find_speffect(chr_ins, *speffect).is_none();
+            let tier_missing = tier.is_some_and(|tier| tier.speffects.iter().any(missing));
+
+            //i don't have to do any extar NG+X X>1 work, since the game seems to magically apply the extra scaling based on the game_data_man.clear_count
+            if scaled.level != Some(level) || missing(&gameclear_speffect) || tier_missing {
+                //get the speffect for NG+ for the enemy, and apply it
+                apply_speffect(chrins_enemy, gameclear_speffect);
+
+                //then the custom tier on top
+                if let Some(tier) = tier {
+                    for speffect in &tier.speffects {
+                        apply_speffect(chrins_enemy, *speffect);
+                    }
                 }
-                if param == 0 {
-                    continue;
+                scaled.level = Some(level);
+            }
+
+            //damage and defense go through the NG+ SpEffect's row, once the game has put it on
+            if let Some(entry) = tier.and_then(|_| find_speffect(chr_ins, gameclear_speffect)) {
+                let row = entry.param_data as usize;
+                if row != 0 && !scaled_rows.contains_key(&row) {
+                    let mut scaled_row = ScaledRow {
+                        original: *entry.param_data,
+                        damage: 1.0,
+                        defense: 1.0,
+                    };
+                    scale_rates(row, &mut scaled_row, damage_multiplier, defense_multiplier);
+                    scaled_rows.insert(row, scaled_row);
                 }
+            }
 
-                let npcparam_st = *((param + 0) as *mut u64);
-                if npcparam_st == 0 {
+            //and the rules that pick this character out, taking off the ones that stopped
+            let mut hp_multiplier = tier.map_or(1.0, |tier| tier.hp_multiplier);
+            let mut matched = Vec::new();
+            for (id, rule) in &rules {
+                if !hostile || !player.is_some_and(|player| rule.target.matches(chr_ins, player)) {
+                    if scaled.rules.contains(id) {
+                        for speffect in &rule.speffects {
+                            remove_speffect(chr_ins, *speffect);
+                        }
+                    }
                     continue;
                 }
-                let gameclear_speffect = *((npcparam_st + 0x6c) as *mut u32);
-                if gameclear_speffect == 0 {
-                    continue;
+
+                if !scaled.rules.contains(id) || rule.speffects.iter().any(missing) {
+                    for speffect in &rule.speffects {
+                        apply_speffect(chrins_enemy, *speffect);
+                    }
                 }
+                hp_multiplier *= rule.hp_multiplier;
+                matched.push(*id);
+            }
+            scaled.rules = matched;
 
-                //i don't have to do any extar NG+X X>1 work, since the game seems to magically apply the extra scaling based on the game_data_man.clear_count
-                //don't have to clear the value either, since it seems the game also does that
+            scale_hp(chr_ins, hp_multiplier, scaled);
+            scale_poise(
+                chr_ins,
+                tier.map_or(1.0, |tier| tier.poise_multiplier),
+                scaled,
+            );
+        }
+    }
 
-                //get the speffect for NG+ for the enemy, and apply it
-                apply_speffect_fn(chrins_enemy, gameclear_speffect, 1);
+    //forget characters that unloaded, they get scaled from scratch when they come back
+    scaled_characters.retain(|_, scaled| scaled.missed <= MISSED_PASSES_BEFORE_GONE);
+}
+
+// Every loaded character around the current player
+pub unsafe fn get_characters(world_chr_man: u64) -> Vec<u64> {
+    read_characters(world_chr_man).unwrap_or_default()
+}
+
+// Same as get_characters, but None when the chr set isn't there to read
+unsafe fn read_characters(world_chr_man: u64) -> Option<Vec<u64>> {
+    let mut characters = Vec::new();
+
+    //get list of all enemies around the current player
+    //This code is taken from inuNorii's Kill All Mobs script in TGA table
+    let mut chr_set = *((world_chr_man + 0x1CC60) as *mut u64); //legacy dungeon
+    if chr_set == 0 {
+        return None;
+    }
+    let open_field_chr_set = *((world_chr_man + 0x1E270) as *mut u64); //open world
+    if open_field_chr_set == 0 {
+        return None;
+    }
+
+    let mut use_legacy = false;
+    let mut chr_count = *((open_field_chr_set + 0x20) as *mut u32);
+    if chr_count == 0xffffffff {
+        chr_count = *((chr_set + 0x10) as *mut u32);
+        use_legacy = true;
+    }
+
+    if use_legacy {
+        chr_set = *((chr_set + 0x18) as *mut u64);
+    } else {
+        chr_set = *((open_field_chr_set + 0x18) as *mut u64);
+    }
+    if chr_set == 0 {
+        return None;
+    }
+
+    for i in 1..chr_count {
+        let chrins_enemy = *((chr_set + (i * 0x10) as u64) as *mut u64);
+        if chrins_enemy != 0 {
+            let chrins_enemy_vtable = *(chrins_enemy as *mut usize);
+            if chrins_enemy_vtable == 0 {
+                continue;
             }
+
+            characters.push(chrins_enemy);
         }
     }
+
+    Some(characters)
 }
 
 fn ng_val_to_msg(ng: u32, isup: bool) -> FullscreenMsgIndex {
@@ -128,7 +814,7 @@ fn ng_val_to_msg(ng: u32, isup: bool) -> FullscreenMsgIndex {
     }
 }
 
-pub fn increase_difficulty() {
+pub fn increase_difficulty(duration_seconds: Option<u64>) {
     let game_data_man = {
         let game_data_man = get_game_data_man();
         if game_data_man.is_none() {
@@ -138,14 +824,50 @@ pub fn increase_difficulty() {
 
         game_data_man.unwrap()
     };
-    if game_data_man.clear_count < 7 {
-        game_data_man.clear_count += 1;
-    }
+    let current = level_to_change(game_data_man, duration_seconds);
+    let next = level_above(current);
+    set_level(
+        game_data_man,
+        next,
+        true,
+        duration_seconds,
+        DifficultySource::Command,
+    );
+}
+
+pub fn decrease_difficulty(duration_seconds: Option<u64>) {
+    let game_data_man = {
+        let game_data_man = get_game_data_man();
+        if game_data_man.is_none() {
+            log::info!("GameDataMan does not have an instance");
+            return;
+        }
 
-    display_message(ng_val_to_msg(game_data_man.clear_count, true));
+        game_data_man.unwrap()
+    };
+
+    let current = level_to_change(game_data_man, duration_seconds);
+    let next = level_below(current);
+    set_level(
+        game_data_man,
+        next,
+        false,
+        duration_seconds,
+        DifficultySource::Command,
+    );
 }
 
-pub fn decrease_difficulty() {
+// Jumps straight to a NG+ level or custom tier, showing a single banner for where it ends up
+pub fn set_difficulty(level: u32, duration_seconds: Option<u64>) {
+    if !is_valid_level(level) {
+        log::info!("Rejecting difficulty {level}, it's above NG+{MAX_NG_LEVEL} and not a tier");
+        send_message(OutgoingMessage::CommandFailed {
+            command: "SetDifficulty".to_string(),
+            reason: format!("level {level} is above NG+{MAX_NG_LEVEL} and not a configured tier"),
+        });
+        return;
+    }
+
     let game_data_man = {
         let game_data_man = get_game_data_man();
         if game_data_man.is_none() {
@@ -156,9 +878,204 @@ pub fn decrease_difficulty() {
         game_data_man.unwrap()
     };
 
-    if game_data_man.clear_count > 0 {
-        game_data_man.clear_count -= 1;
+    //a timed modifier at the current level still holds it there for a while
+    let previous = level_to_change(game_data_man, duration_seconds);
+    if previous == level && duration_seconds.is_none() {
+        return;
+    }
+
+    set_level(
+        game_data_man,
+        level,
+        level > previous,
+        duration_seconds,
+        DifficultySource::Command,
+    );
+}
+
+pub fn get_difficulty() {
+    let Some(game_data_man) = get_game_data_man() else {
+        log::info!("GameDataMan does not have an instance");
+        return;
+    };
+
+    send_message(OutgoingMessage::Difficulty {
+        level: get_level(game_data_man),
+    });
+}
+
+/// Settings for letting the difficulty move by itself, based on how the player is doing
+#[derive(Debug, Clone, Deserialize)]
+pub struct DynamicDifficulty {
+    pub min_level: u32,
+    pub max_level: u32,
+    // Drop a level after this many deaths on the same level, 0 to never drop
+    #[serde(default)]
+    pub deaths_to_lower: u32,
+    // Go up a level after this long without dying or the level changing, 0 to never go up
+    #[serde(default)]
+    pub raise_after_seconds: u64,
+    // Killing any of these goes up a level. There's no known boss flag, so bosses are listed.
+    #[serde(default)]
+    pub boss_npc_ids: Vec<i32>,
+}
+
+struct DynamicState {
+    settings: DynamicDifficulty,
+    deaths: u32,
+    // Last death or level change, whichever came later
+    since: Instant,
+    player_dead: bool,
+    boss_hp: HashMap<(i32, MapId), (i32, u32)>,
+}
+
+static DYNAMIC_DIFFICULTY: Mutex<Option<DynamicState>> = Mutex::new(None);
+
+// Turns dynamic difficulty on with the given settings, or off when there are none
+pub fn set_dynamic_difficulty(settings: Option<DynamicDifficulty>) {
+    log::info!("Setting dynamic difficulty to {settings:?}");
+
+    if let Some(settings) = &settings {
+        let reason = if settings.min_level > settings.max_level {
+            Some("min_level is above max_level".to_string())
+        } else {
+            [settings.min_level, settings.max_level]
+                .into_iter()
+                .find(|level| !is_valid_level(*level))
+                .map(|level| format!("level {level} is above NG+{MAX_NG_LEVEL} and not a tier"))
+        };
+
+        if let Some(reason) = reason {
+            send_message(OutgoingMessage::CommandFailed {
+                command: "SetDynamicDifficulty".to_string(),
+                reason,
+            });
+            return;
+        }
+    }
+
+    *DYNAMIC_DIFFICULTY.lock().unwrap() = settings.map(|settings| DynamicState {
+        settings,
+        deaths: 0,
+        since: Instant::now(),
+        player_dead: false,
+        boss_hp: HashMap::new(),
+    });
+}
+
+// Moves the difficulty when dynamic difficulty is on. Deaths are counted when the player's hp
+// hits 0 and bosses are killed when theirs does.
+pub fn adjust_difficulty() {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            return;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            return;
+        }
+    }
+
+    let mut dynamic = DYNAMIC_DIFFICULTY.lock().unwrap();
+    let Some(state) = dynamic.as_mut() else {
+        return;
+    };
+
+    let Ok(Some(world_chr_man)) = get_instance::<WorldChrMan>() else {
+        return;
+    };
+    let Some(game_data_man) = get_game_data_man() else {
+        return;
+    };
+    //while a timed modifier holds the level, move the one that comes back after it
+    let level = permanent_level(game_data_man);
+
+    let mut reason = None;
+
+    //a death is the moment hp hits 0, not every frame it stays there
+    let player_dead = world_chr_man.main_player.module_container.data.hp == 0;
+    if player_dead && !state.player_dead {
+        state.deaths += 1;
+        state.since = Instant::now();
+        if state.settings.deaths_to_lower > 0 && state.deaths >= state.settings.deaths_to_lower {
+            reason = Some(DifficultyChangeReason::Deaths {
+                count: state.deaths,
+            });
+        }
+    }
+    state.player_dead = player_dead;
+
+    if !state.settings.boss_npc_ids.is_empty() {
+        let mut seen = HashSet::new();
+        if let Some(world_chr_man) = get_world_chr_man().filter(|chr_man| *chr_man != 0) {
+            for chr_ins in unsafe { get_characters(world_chr_man) } {
+                let chr_ins = unsafe { &*(chr_ins as *const ChrIns) };
+                if !state.settings.boss_npc_ids.contains(&chr_ins.npc_id_1) {
+                    continue;
+                }
+
+                let handle = (
+                    chr_ins.field_ins_handle.instance_id,
+                    chr_ins.field_ins_handle.map_id,
+                );
+                let hp = chr_ins.module_container.data.hp;
+                if let Some((npc_id, last_hp)) =
+                    state.boss_hp.insert(handle, (chr_ins.npc_id_1, hp))
+                {
+                    if last_hp > 0 && hp == 0 {
+                        reason = Some(DifficultyChangeReason::BossKilled { npc_id });
+                    }
+                }
+                seen.insert(handle);
+            }
+        }
+        state.boss_hp.retain(|handle, _| seen.contains(handle));
+    }
+
+    let survived = state.since.elapsed();
+    if reason.is_none()
+        && state.settings.raise_after_seconds > 0
+        && survived >= Duration::from_secs(state.settings.raise_after_seconds)
+    {
+        reason = Some(DifficultyChangeReason::Survived {
+            seconds: survived.as_secs(),
+        });
+    }
+
+    let Some(reason) = reason else {
+        return;
+    };
+
+    let lower = matches!(reason, DifficultyChangeReason::Deaths { .. });
+    let next = if lower {
+        level_below(level).max(state.settings.min_level)
+    } else {
+        level_above(level).min(state.settings.max_level)
+    };
+
+    //a level change, or hitting the bounds, starts the counting over
+    state.deaths = 0;
+    state.since = Instant::now();
+
+    //outside the bounds the clamp would pull the level the other way, leave it to the commands
+    if (lower && next >= level) || (!lower && next <= level) {
+        return;
     }
 
-    display_message(ng_val_to_msg(game_data_man.clear_count, false));
+    log::info!("Dynamic difficulty moving from {level} to {next}: {reason:?}");
+    set_level(
+        game_data_man,
+        next,
+        next > level,
+        None,
+        DifficultySource::Dynamic,
+    );
+    send_message(OutgoingMessage::DynamicDifficultyEvent {
+        old: level,
+        new: next,
+        reason,
+    });
 }
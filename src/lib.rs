@@ -12,6 +12,7 @@ use std::{
         Mutex,
     },
     thread::spawn,
+    time::{Duration, Instant},
 };
 use tungstenite::{accept, Message};
 use windows::Win32::System::Diagnostics::Debug::{
@@ -19,14 +20,22 @@ use windows::Win32::System::Diagnostics::Debug::{
 };
 
 const WS_PORT: &str = "10001";
+// How often changes to the blood message journal are written to disk
+const JOURNAL_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Bindings to the bloodmessage system
 mod bloodmessage;
 mod difficulty;
+/// Placeholders and markup in blood message text
+mod messagetext;
+/// Catalogue of the blood message sign visuals
+mod messagevisual;
 /// Bindings to the player
 mod player;
 /// Service locator using FS's DLRF system
 mod reflection;
+/// Applying and removing arbitrary SpEffects
+mod speffect;
 mod task;
 mod util;
 
@@ -137,12 +146,89 @@ pub fn entry(_hmodule: usize) -> bool {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 pub enum IncomingMessage {
-    SpawnBloodMessage { text: String, msg_visual: i32 },
-    RemoveBloodMessage { text: String },
-    IncreaseDifficulty,
-    DecreaseDifficulty,
+    SpawnBloodMessage {
+        text: String,
+        msg_visual: messagevisual::VisualRequest,
+        tag: Option<String>,
+        ttl_seconds: Option<u64>,
+        #[serde(flatten)]
+        placement: bloodmessage::MessagePlacement,
+    },
+    SpawnBloodMessageFormation {
+        texts: Vec<String>,
+        shape: bloodmessage::FormationShape,
+        msg_visual: messagevisual::VisualRequest,
+        tag: Option<String>,
+        ttl_seconds: Option<u64>,
+    },
+    RemoveBloodMessage {
+        text: String,
+    },
+    RemoveBloodMessageById {
+        id: u16,
+    },
+    RemoveBloodMessagesByTag {
+        tag: String,
+    },
+    UpdateBloodMessage {
+        id: u16,
+        text: String,
+    },
+    SetMessageVariable {
+        name: String,
+        value: String,
+    },
+    ForgetBloodMessage {
+        id: u16,
+    },
+    ListBloodMessages,
+    ListMessageVisuals,
+    SetUncheckedMessageVisuals {
+        allowed: bool,
+    },
+    ListWorldMessages {
+        radius: Option<f32>,
+    },
+    SetBloodMessageLimit {
+        max: usize,
+    },
+    IncreaseDifficulty {
+        duration_seconds: Option<u64>,
+    },
+    DecreaseDifficulty {
+        duration_seconds: Option<u64>,
+    },
+    SetDifficulty {
+        level: u32,
+        duration_seconds: Option<u64>,
+    },
+    GetDifficulty,
+    ListDifficultyModifiers,
+    AddScalingRule {
+        #[serde(flatten)]
+        rule: difficulty::ScalingRule,
+    },
+    RemoveScalingRule {
+        id: u32,
+    },
+    ListScalingRules,
+    SetDynamicDifficulty {
+        settings: Option<difficulty::DynamicDifficulty>,
+    },
     GetPlayerSpiritPosition,
-    SetSpiritScale { size: f32, power: f32 },
+    ApplySpEffect {
+        speffect: u32,
+        target: speffect::SpEffectTarget,
+        duration_seconds: Option<u64>,
+    },
+    RemoveSpEffect {
+        speffect: u32,
+        target: speffect::SpEffectTarget,
+    },
+    SetSpiritScale {
+        size: f32,
+        power: f32,
+    },
 }
 
 lazy_static! {
@@ -153,13 +239,72 @@ fn handle_client_task() {
     if let Some(recv_in) = TASK_ENQUEUE.lock().unwrap().as_ref() {
         while let Ok(msg) = recv_in.try_recv() {
             match msg {
-                IncomingMessage::SpawnBloodMessage { text, msg_visual } => {
-                    bloodmessage::spawn_message(&text, msg_visual)
-                }
+                IncomingMessage::SpawnBloodMessage {
+                    text,
+                    msg_visual,
+                    tag,
+                    ttl_seconds,
+                    placement,
+                } => bloodmessage::spawn_message(&text, &msg_visual, tag, ttl_seconds, &placement),
+                IncomingMessage::SpawnBloodMessageFormation {
+                    texts,
+                    shape,
+                    msg_visual,
+                    tag,
+                    ttl_seconds,
+                } => bloodmessage::spawn_formation(&texts, &shape, &msg_visual, tag, ttl_seconds),
                 IncomingMessage::RemoveBloodMessage { text } => bloodmessage::delete_message(&text),
-                IncomingMessage::IncreaseDifficulty => difficulty::increase_difficulty(),
-                IncomingMessage::DecreaseDifficulty => difficulty::decrease_difficulty(),
+                IncomingMessage::RemoveBloodMessageById { id } => {
+                    bloodmessage::delete_message_by_id(id)
+                }
+                IncomingMessage::RemoveBloodMessagesByTag { tag } => {
+                    bloodmessage::delete_messages_by_tag(&tag)
+                }
+                IncomingMessage::UpdateBloodMessage { id, text } => {
+                    bloodmessage::update_message(id, &text)
+                }
+                IncomingMessage::SetMessageVariable { name, value } => {
+                    messagetext::set_variable(name, value)
+                }
+                IncomingMessage::ForgetBloodMessage { id } => bloodmessage::forget_message(id),
+                IncomingMessage::ListBloodMessages => bloodmessage::list_messages(),
+                IncomingMessage::ListWorldMessages { radius } => {
+                    bloodmessage::list_world_messages(radius)
+                }
+                IncomingMessage::ListMessageVisuals => messagevisual::list_visuals(),
+                IncomingMessage::SetUncheckedMessageVisuals { allowed } => {
+                    messagevisual::set_unchecked_visuals(allowed)
+                }
+                IncomingMessage::SetBloodMessageLimit { max } => {
+                    bloodmessage::set_message_limit(max)
+                }
+                IncomingMessage::IncreaseDifficulty { duration_seconds } => {
+                    difficulty::increase_difficulty(duration_seconds)
+                }
+                IncomingMessage::DecreaseDifficulty { duration_seconds } => {
+                    difficulty::decrease_difficulty(duration_seconds)
+                }
+                IncomingMessage::SetDifficulty {
+                    level,
+                    duration_seconds,
+                } => difficulty::set_difficulty(level, duration_seconds),
+                IncomingMessage::GetDifficulty => difficulty::get_difficulty(),
+                IncomingMessage::ListDifficultyModifiers => difficulty::list_modifiers(),
+                IncomingMessage::AddScalingRule { rule } => difficulty::add_scaling_rule(rule),
+                IncomingMessage::RemoveScalingRule { id } => difficulty::remove_scaling_rule(id),
+                IncomingMessage::ListScalingRules => difficulty::list_scaling_rules(),
+                IncomingMessage::SetDynamicDifficulty { settings } => {
+                    difficulty::set_dynamic_difficulty(settings)
+                }
                 IncomingMessage::GetPlayerSpiritPosition => util::report_position(),
+                IncomingMessage::ApplySpEffect {
+                    speffect,
+                    target,
+                    duration_seconds,
+                } => speffect::apply_speffect(speffect, &target, duration_seconds),
+                IncomingMessage::RemoveSpEffect { speffect, target } => {
+                    speffect::remove_speffect_from(speffect, &target)
+                }
                 IncomingMessage::SetSpiritScale { size, power } => spiritash::set_size(size, power),
             }
         }
@@ -190,6 +335,42 @@ pub fn handle_client(stream: TcpStream) {
         CSTaskGroupIndex::WorldChrMan_PostPhysics,
     );
 
+    // Start the task to handle moving the difficulty by itself
+    let dynamic_difficulty = task::run_task(
+        difficulty::adjust_difficulty,
+        CSTaskGroupIndex::WorldChrMan_PostPhysics,
+    );
+
+    // Start the task to handle timed difficulty modifiers running out
+    let modifier_expiry = task::run_task(
+        difficulty::expire_modifiers,
+        CSTaskGroupIndex::WorldChrMan_PostPhysics,
+    );
+
+    // Start the task to handle expiring blood messages
+    let message_expiry = task::run_task(
+        bloodmessage::expire_messages,
+        CSTaskGroupIndex::WorldChrMan_PostPhysics,
+    );
+
+    // Start the task to handle respawning journaled blood messages
+    let message_restore = task::run_task(
+        bloodmessage::restore_messages,
+        CSTaskGroupIndex::WorldChrMan_PostPhysics,
+    );
+
+    // Start the task to handle players walking up to blood messages
+    let message_reads = task::run_task(
+        bloodmessage::detect_message_reads,
+        CSTaskGroupIndex::WorldChrMan_PostPhysics,
+    );
+
+    // Start the task to handle timed SpEffects running out
+    let speffect_expiry = task::run_task(
+        speffect::expire_speffects,
+        CSTaskGroupIndex::WorldChrMan_PostPhysics,
+    );
+
     // Start the task to handle reporting spirit ash events
     let spirit_report = task::run_task(
         spiritash::get_status,
@@ -203,7 +384,14 @@ pub fn handle_client(stream: TcpStream) {
     let mut websocket = accept(stream.try_clone().expect("tcpstream clone failed..."))
         .expect("Could not accept stream");
 
+    let mut last_journal_flush = Instant::now();
     loop {
+        //write out blood message journal changes every so often, off the game thread
+        if last_journal_flush.elapsed() >= JOURNAL_FLUSH_INTERVAL {
+            bloodmessage::flush_journal();
+            last_journal_flush = Instant::now();
+        }
+
         //listen for data from the game for messages being read, or other events, and pass it back to the remote client
         if let Ok(msg) = gamepush_recv.try_recv() {
             log::info!("Pushing message {msg:?}");
@@ -240,9 +428,16 @@ pub fn handle_client(stream: TcpStream) {
         }
     }
 
+    bloodmessage::flush_journal();
     *GAMEPUSH_SEND.lock().unwrap() = None;
     *TASK_ENQUEUE.lock().unwrap() = None;
     drop(spirit_report);
+    drop(speffect_expiry);
+    drop(message_expiry);
+    drop(message_restore);
+    drop(message_reads);
+    drop(modifier_expiry);
+    drop(dynamic_difficulty);
     drop(task_scaling);
     drop(task_msgs);
 }
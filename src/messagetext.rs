@@ -0,0 +1,278 @@
+use crate::difficulty;
+use crate::spiritash;
+use crate::util::{get_game_data_man, unix_time};
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+
+// Values pushed by the client for the {var:name} placeholder
+static MESSAGE_VARIABLES: LazyLock<RwLock<HashMap<String, String>>> =
+    LazyLock::new(Default::default);
+
+pub fn set_variable(name: String, value: String) {
+    log::info!("Setting message variable {name:?} to {value:?}");
+
+    MESSAGE_VARIABLES.write().unwrap().insert(name, value);
+}
+
+// Names fill_placeholders knows how to fill in
+const PLACEHOLDERS: &[&str] = &["ng", "spirits", "countdown", "var"];
+
+/// Whether the text has any placeholders that need rendering. Braces that aren't one of ours
+/// don't count, so plain text isn't re-rendered on every lookup.
+pub fn is_dynamic(template: &str) -> bool {
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            return false;
+        };
+
+        if PLACEHOLDERS.contains(&placeholder_name(&after[..end]).0) {
+            return true;
+        }
+        rest = &after[end + 1..];
+    }
+
+    false
+}
+
+fn placeholder_name(placeholder: &str) -> (&str, Option<&str>) {
+    match placeholder.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (placeholder, None),
+    }
+}
+
+/// Turns client text into what the game gets to show, placeholders filled in and markup translated.
+pub fn render(template: &str) -> String {
+    to_game_markup(&fill_placeholders(template))
+}
+
+/// Fills in the placeholders in a message. Supported are
+/// - `{ng}` the current difficulty level, including custom tiers past NG+7
+/// - `{spirits}` how many spirit ashes are summoned
+/// - `{countdown:<unix timestamp>}` minutes and seconds left until the timestamp
+/// - `{var:<name>}` a value set by the client with SetMessageVariable
+///
+/// Anything else between braces is left alone.
+fn fill_placeholders(template: &str) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let placeholder = &after[..end];
+        match render_placeholder(placeholder) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+fn render_placeholder(placeholder: &str) -> Option<String> {
+    match placeholder_name(placeholder) {
+        ("ng", None) => get_game_data_man().map(|gdm| difficulty::get_level(gdm).to_string()),
+        ("spirits", None) => spiritash::get_position().map(|spirits| spirits.len().to_string()),
+        ("countdown", Some(until)) => {
+            let until = until.parse::<u64>().ok()?;
+            let remaining = until.saturating_sub(unix_time());
+            Some(format!("{}:{:02}", remaining / 60, remaining % 60))
+        }
+        ("var", Some(variable)) => MESSAGE_VARIABLES.read().unwrap().get(variable).cloned(),
+        _ => None,
+    }
+}
+
+// Highlight color used for [em]
+const EMPHASIS_COLOR: &str = "#E0B985";
+
+const NAMED_COLORS: &[(&str, &str)] = &[
+    ("red", "#D04040"),
+    ("orange", "#E08A3C"),
+    ("yellow", "#E8D55A"),
+    ("green", "#6CC060"),
+    ("blue", "#5A9BE0"),
+    ("purple", "#A070D0"),
+    ("white", "#FFFFFF"),
+    ("gray", "#A0A0A0"),
+];
+
+/// Translates the small markup clients are allowed to use into the font tags the game's text
+/// renderer understands. Everything else that could be read as a tag is escaped, so viewers can't
+/// sneak their own markup in.
+/// - `[color=#rrggbb]...[/color]` or one of the NAMED_COLORS, e.g. `[color=red]`
+/// - `[em]...[/em]` highlights the text
+/// - `[br]` or a newline breaks the line
+///
+/// Tags that don't match up are shown as typed, tags left open are closed at the end.
+fn to_game_markup(text: &str) -> String {
+    #[derive(PartialEq)]
+    enum Open {
+        Color,
+        Emphasis,
+    }
+
+    let mut markup = String::with_capacity(text.len());
+    let mut open = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        escape_into(&mut markup, &rest[..start]);
+
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(']') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let tag = &after[..end];
+        match tag {
+            "br" => markup.push('\n'),
+            "em" => {
+                markup.push_str(&format!("<font color=\"{EMPHASIS_COLOR}\">"));
+                open.push(Open::Emphasis);
+            }
+            "/em" if open.last() == Some(&Open::Emphasis) => {
+                markup.push_str("</font>");
+                open.pop();
+            }
+            "/color" if open.last() == Some(&Open::Color) => {
+                markup.push_str("</font>");
+                open.pop();
+            }
+            _ => match tag.strip_prefix("color=").and_then(parse_color) {
+                Some(color) => {
+                    markup.push_str(&format!("<font color=\"{color}\">"));
+                    open.push(Open::Color);
+                }
+                None => {
+                    //not one of ours, show it as typed
+                    markup.push('[');
+                    escape_into(&mut markup, tag);
+                    markup.push(']');
+                }
+            },
+        }
+        rest = &after[end + 1..];
+    }
+    escape_into(&mut markup, rest);
+
+    for _ in open {
+        markup.push_str("</font>");
+    }
+
+    markup
+}
+
+/// Accepts `#rrggbb` or a named color, giving back the `#RRGGBB` the game wants.
+fn parse_color(color: &str) -> Option<String> {
+    if let Some((_, hex)) = NAMED_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(color))
+    {
+        return Some(hex.to_string());
+    }
+
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(format!("#{}", hex.to_ascii_uppercase()))
+}
+
+fn escape_into(markup: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '<' => markup.push_str("&lt;"),
+            '>' => markup.push_str("&gt;"),
+            '&' => markup.push_str("&amp;"),
+            _ => markup.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unclosed_brace_is_kept_once() {
+        assert_eq!(fill_placeholders("hi :{"), "hi :{");
+        assert_eq!(fill_placeholders("a {b} c {"), "a {b} c {");
+    }
+
+    #[test]
+    fn only_known_placeholders_are_dynamic() {
+        assert!(is_dynamic("NG+{ng}"));
+        assert!(is_dynamic("{countdown:1700000000} left"));
+        assert!(!is_dynamic("hi :{"));
+        assert!(!is_dynamic("a {b} c"));
+    }
+
+    #[test]
+    fn raw_tags_are_escaped() {
+        assert_eq!(
+            to_game_markup("<font color=\"#FF0000\">x</font> & y"),
+            "&lt;font color=\"#FF0000\"&gt;x&lt;/font&gt; &amp; y"
+        );
+    }
+
+    #[test]
+    fn tags_that_dont_match_up_are_shown_as_typed() {
+        assert_eq!(to_game_markup("[/em]x[/color]"), "[/em]x[/color]");
+        assert_eq!(
+            to_game_markup("[em]a[/color]b[/em]"),
+            "<font color=\"#E0B985\">a[/color]b</font>"
+        );
+        assert_eq!(
+            to_game_markup("[color=red]a[/em]b[/color]"),
+            "<font color=\"#D04040\">a[/em]b</font>"
+        );
+    }
+
+    #[test]
+    fn unknown_colors_are_shown_as_typed() {
+        assert_eq!(
+            to_game_markup("[color=nope]x[/color]"),
+            "[color=nope]x[/color]"
+        );
+        assert_eq!(
+            to_game_markup("[color=#12345]x[/color]"),
+            "[color=#12345]x[/color]"
+        );
+        assert_eq!(to_game_markup("[color=<b>]x"), "[color=&lt;b&gt;]x");
+    }
+
+    #[test]
+    fn open_tags_are_closed_at_the_end() {
+        assert_eq!(
+            to_game_markup("[color=#a0b0c0][em]x"),
+            "<font color=\"#A0B0C0\"><font color=\"#E0B985\">x</font></font>"
+        );
+    }
+
+    #[test]
+    fn placeholder_values_are_escaped() {
+        set_variable(
+            "markup_test".to_string(),
+            "<font color=\"#FF0000\">x</font>".to_string(),
+        );
+        assert_eq!(
+            render("hi {var:markup_test}"),
+            "hi &lt;font color=\"#FF0000\"&gt;x&lt;/font&gt;"
+        );
+    }
+}
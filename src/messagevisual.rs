@@ -0,0 +1,81 @@
+use crate::util::{send_message, MessageVisualInfo, OutgoingMessage};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+pub struct MessageVisual {
+    pub name: &'static str,
+    pub id: i32,
+    pub description: &'static str,
+}
+
+/// The visual the game uses for ordinary messages
+pub const STANDARD_VISUAL: i32 = 30;
+
+/// Every message_sign_visual we know is safe to hand to the game. Plenty of ids render nothing or
+/// crash the game outright, so only add ids here after checking them in game (the commented out
+/// sweep in the test client is handy for that). Only the standard sign has been checked so far,
+/// other ids need SetUncheckedMessageVisuals.
+pub const MESSAGE_VISUALS: &[MessageVisual] = &[MessageVisual {
+    name: "Standard",
+    id: STANDARD_VISUAL,
+    description: "The regular glowing message sign",
+}];
+
+// Whether raw ids outside the catalogue are passed to the game as they are
+static UNCHECKED_VISUALS: AtomicBool = AtomicBool::new(false);
+
+/// Lets clients that relied on sending any raw id keep doing so, at their own risk.
+pub fn set_unchecked_visuals(allowed: bool) {
+    log::info!("Setting unchecked message visuals to {allowed}");
+
+    UNCHECKED_VISUALS.store(allowed, Ordering::Relaxed);
+}
+
+/// The visual a client asked for, either by catalogue name or by raw id.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum VisualRequest {
+    Id(i32),
+    Name(String),
+}
+
+#[derive(Debug, Error)]
+pub enum VisualError {
+    #[error("message visual id {0} is not in the catalogue and unchecked visuals are off")]
+    UnknownId(i32),
+    #[error("there is no message visual called {0:?}")]
+    UnknownName(String),
+}
+
+impl VisualRequest {
+    /// Looks the visual up in the catalogue, rejecting anything we don't know.
+    pub fn resolve(&self) -> Result<i32, VisualError> {
+        match self {
+            VisualRequest::Id(id) => MESSAGE_VISUALS
+                .iter()
+                .find(|visual| visual.id == *id)
+                .map(|visual| visual.id)
+                .or(UNCHECKED_VISUALS.load(Ordering::Relaxed).then_some(*id))
+                .ok_or(VisualError::UnknownId(*id)),
+            VisualRequest::Name(name) => MESSAGE_VISUALS
+                .iter()
+                .find(|visual| visual.name.eq_ignore_ascii_case(name))
+                .map(|visual| visual.id)
+                .ok_or_else(|| VisualError::UnknownName(name.clone())),
+        }
+    }
+}
+
+pub fn list_visuals() {
+    send_message(OutgoingMessage::MessageVisualList {
+        visuals: MESSAGE_VISUALS
+            .iter()
+            .map(|visual| MessageVisualInfo {
+                name: visual.name.to_string(),
+                id: visual.id,
+                description: visual.description.to_string(),
+            })
+            .collect(),
+    });
+}
@@ -1,13 +1,14 @@
 use crate::reflection::get_instance;
 use crate::reflection::DLRFLocatable;
 use crate::util::{get_field_area, get_game_base, CameraInfo, Position};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct Vector4(pub f32, pub f32, pub f32, pub f32);
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct MapId {
     pub index: u8,
     pub region: u8,
@@ -109,7 +110,7 @@ pub struct ChrInsModuleContainer<'a> {
     pub behavior: &'a mut CSChrBehaviorModule,
     pub behavior_sync: usize,
     pub ai: usize,
-    pub super_armor: usize,
+    pub super_armor: &'a mut CSChrSuperArmorModule,
     pub toughness: usize,
     pub talk: usize,
     pub event: usize,
@@ -164,6 +165,16 @@ pub struct ChrDataModule {
 const _: () = assert!(std::mem::offset_of!(ChrDataModule, hp) == 0x138);
 const _: () = assert!(std::mem::offset_of!(ChrDataModule, recoverable_hp_left1) == 0x160);
 
+// Poise, the damage a character takes before it staggers
+#[repr(C)]
+pub struct CSChrSuperArmorModule {
+    pub vftable: usize,
+    owner: usize,
+    pub sa_durability: f32,
+    pub sa_durability_max: f32,
+}
+const _: () = assert!(std::mem::offset_of!(CSChrSuperArmorModule, sa_durability_max) == 0x14);
+
 #[repr(C)]
 pub struct ChrRideModule {
     pub vftable: usize,
@@ -207,6 +218,95 @@ pub struct ChrCtrl<'a> {
 const _: () = assert!(std::mem::offset_of!(ChrCtrl, chr_ragdoll_state) == 0x128);
 const _: () = assert!(std::mem::offset_of!(ChrCtrl, scale_size) == 0x2d4);
 
+// What ChrIns.special_effect points to, the SpEffects active on a character
+#[repr(C)]
+pub struct SpecialEffect {
+    pub vftable: usize,
+    pub head: *mut SpecialEffectEntry,
+}
+
+#[repr(C)]
+pub struct SpecialEffectEntry {
+    pub param_data: *mut SpEffectParamRates,
+    pub param_id: u32,
+    _pad: u32,
+    unk10: [u8; 0x20],
+    pub next: *mut SpecialEffectEntry,
+    pub previous: *mut SpecialEffectEntry,
+    pub duration: f32,
+}
+const _: () = assert!(std::mem::offset_of!(SpecialEffectEntry, next) == 0x30);
+const _: () = assert!(std::mem::offset_of!(SpecialEffectEntry, duration) == 0x40);
+
+// The start of a SpEffectParam row, up to the attack power rates. Dark (holy) rates sit further
+// into the row and aren't mapped.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpEffectParamRates {
+    unk0: [u8; 0x1c],
+    // Multipliers on the damage the character takes
+    pub slash_damage_cut_rate: f32,
+    pub blow_damage_cut_rate: f32,
+    pub thrust_damage_cut_rate: f32,
+    pub neutral_damage_cut_rate: f32,
+    pub magic_damage_cut_rate: f32,
+    pub fire_damage_cut_rate: f32,
+    pub thunder_damage_cut_rate: f32,
+    unk38: [u8; 0x10],
+    // Multipliers on the damage the character deals
+    pub physics_attack_power_rate: f32,
+    pub magic_attack_power_rate: f32,
+    pub fire_attack_power_rate: f32,
+    pub thunder_attack_power_rate: f32,
+}
+const _: () = assert!(std::mem::offset_of!(SpEffectParamRates, slash_damage_cut_rate) == 0x1c);
+const _: () = assert!(std::mem::offset_of!(SpEffectParamRates, physics_attack_power_rate) == 0x48);
+
+// The entry for a SpEffect on a character, if it has it
+pub fn find_speffect<'a>(chr_ins: &ChrIns, speffect: u32) -> Option<&'a mut SpecialEffectEntry> {
+    let special_effect = chr_ins.special_effect as *const SpecialEffect;
+    if special_effect.is_null() {
+        return None;
+    }
+
+    unsafe {
+        let mut current_ptr = (*special_effect).head;
+        while !current_ptr.is_null() {
+            let current = &mut *current_ptr;
+            if current.param_id == speffect && current.duration != 0.0 {
+                return Some(current);
+            }
+            current_ptr = current.next;
+        }
+    }
+
+    None
+}
+
+// Ends a SpEffect on a character by running its duration out, the game removes it and undoes
+// its stats on its next update. Returns whether the character had it.
+pub fn remove_speffect(chr_ins: &ChrIns, speffect: u32) -> bool {
+    let special_effect = chr_ins.special_effect as *const SpecialEffect;
+    if special_effect.is_null() {
+        return false;
+    }
+
+    let mut found = false;
+    unsafe {
+        let mut current_ptr = (*special_effect).head;
+        while !current_ptr.is_null() {
+            let current = &mut *current_ptr;
+            if current.param_id == speffect {
+                current.duration = 0.0;
+                found = true;
+            }
+            current_ptr = current.next;
+        }
+    }
+
+    found
+}
+
 #[repr(C)]
 pub struct WorldChrMan<'a> {
     pub vftable: usize,
@@ -0,0 +1,181 @@
+use crate::difficulty::get_characters;
+use crate::player::{remove_speffect, ChrIns, MapId, PlayerIns, WorldChrMan};
+use crate::reflection::get_instance;
+use crate::spiritash;
+use crate::util::{
+    self, get_game_base, get_world_chr_man, is_hostile, send_message, within_radius,
+    OutgoingMessage,
+};
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Who a SpEffect command goes to
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum SpEffectTarget {
+    Player,
+    AllEnemies,
+    EnemiesInRadius { radius: f32 },
+    LockedOn,
+    Spirit { id: i32 },
+}
+
+// A SpEffect to take back off a character once its time is up
+struct TimedSpEffect {
+    handle: (i32, MapId),
+    speffect: u32,
+    until: Instant,
+}
+
+static TIMED_SPEFFECTS: Mutex<Vec<TimedSpEffect>> = Mutex::new(Vec::new());
+
+// Applies a SpEffect to every character the target covers. With a duration it's taken off again
+// after that long, otherwise the SpEffect's own param decides how long it lasts.
+pub fn apply_speffect(speffect: u32, target: &SpEffectTarget, duration_seconds: Option<u64>) {
+    let Some(characters) = resolve_target(target) else {
+        return;
+    };
+
+    if characters.is_empty() {
+        log::info!("No characters to apply SpEffect {speffect} to for {target:?}");
+        send_message(OutgoingMessage::CommandFailed {
+            command: "ApplySpEffect".to_string(),
+            reason: format!("no characters match {target:?}"),
+        });
+        return;
+    }
+
+    let until = duration_seconds.map(|seconds| Instant::now() + Duration::from_secs(seconds));
+    let mut timed = TIMED_SPEFFECTS.lock().unwrap();
+    for chr_ins in &characters {
+        util::apply_speffect(*chr_ins as u64, speffect);
+
+        if let Some(until) = until {
+            let handle = unsafe { &(**chr_ins).field_ins_handle };
+            timed.push(TimedSpEffect {
+                handle: (handle.instance_id, handle.map_id),
+                speffect,
+                until,
+            });
+        }
+    }
+
+    log::info!(
+        "Applied SpEffect {speffect} to {} characters",
+        characters.len()
+    );
+    send_message(OutgoingMessage::SpEffectApplied {
+        speffect,
+        count: characters.len(),
+    });
+}
+
+pub fn remove_speffect_from(speffect: u32, target: &SpEffectTarget) {
+    let Some(characters) = resolve_target(target) else {
+        return;
+    };
+
+    let count = characters
+        .into_iter()
+        .filter(|chr_ins| remove_speffect(unsafe { &**chr_ins }, speffect))
+        .count();
+
+    log::info!("Removed SpEffect {speffect} from {count} characters");
+    send_message(OutgoingMessage::SpEffectRemoved { speffect, count });
+}
+
+// Takes timed SpEffects back off. Characters that unloaded in the meantime are skipped, they come
+// back without it anyway.
+pub fn expire_speffects() {
+    let mut timed = TIMED_SPEFFECTS.lock().unwrap();
+    let now = Instant::now();
+    if timed.iter().all(|timed| timed.until > now) {
+        return;
+    }
+
+    let Some(characters) = resolve_target(&SpEffectTarget::AllEnemies) else {
+        return;
+    };
+    let player = get_instance::<WorldChrMan>()
+        .ok()
+        .flatten()
+        .map(|world_chr_man| world_chr_man.main_player as *const ChrIns as *mut ChrIns);
+
+    timed.retain(|timed| {
+        if timed.until > now {
+            return true;
+        }
+
+        let chr_ins = characters
+            .iter()
+            .copied()
+            .chain(player)
+            .chain(spiritash::get_spirit(timed.handle.0))
+            .find(|chr_ins| {
+                let handle = unsafe { &(**chr_ins).field_ins_handle };
+                (handle.instance_id, handle.map_id) == timed.handle
+            });
+        if let Some(chr_ins) = chr_ins {
+            remove_speffect(unsafe { &*chr_ins }, timed.speffect);
+        }
+        false
+    });
+}
+
+// The characters a target covers right now, None while loading
+fn resolve_target(target: &SpEffectTarget) -> Option<Vec<*mut ChrIns<'static>>> {
+    let base = get_game_base().expect("Could not acquire game base");
+    unsafe {
+        //check if we're loading
+        let loading_helper = *((base + 0x3d60ec8) as *mut u64);
+        if loading_helper == 0 {
+            return None;
+        }
+        let loaded = *((loading_helper + 0xED) as *mut u8);
+        if loaded != 1 {
+            return None;
+        }
+    }
+
+    let Ok(Some(world_chr_man)) = get_instance::<WorldChrMan>() else {
+        log::info!("WorldChrMan does not have an instance");
+        return None;
+    };
+    let player = world_chr_man.main_player;
+
+    //the character list has the player, phantoms and friendly NPCs in it too
+    let enemies = || -> Vec<*mut ChrIns<'static>> {
+        match get_world_chr_man().filter(|world_chr_man| *world_chr_man != 0) {
+            Some(world_chr_man) => unsafe { get_characters(world_chr_man) }
+                .into_iter()
+                .map(|chr_ins| chr_ins as *mut ChrIns)
+                .filter(|chr_ins| is_hostile(unsafe { &**chr_ins }))
+                .collect(),
+            None => Vec::new(),
+        }
+    };
+
+    let characters = match target {
+        SpEffectTarget::Player => vec![player as *const ChrIns as *mut ChrIns],
+        SpEffectTarget::AllEnemies => enemies(),
+        SpEffectTarget::EnemiesInRadius { radius } => enemies()
+            .into_iter()
+            .filter(|chr_ins| within_radius(unsafe { &**chr_ins }, player, *radius))
+            .collect(),
+        SpEffectTarget::LockedOn => {
+            let player = unsafe { &*(player as *const ChrIns as *const PlayerIns) };
+            let locked_on = &player.locked_on_enemy_field_ins_handle;
+            enemies()
+                .into_iter()
+                .filter(|chr_ins| {
+                    let handle = unsafe { &(**chr_ins).field_ins_handle };
+                    handle.instance_id == locked_on.instance_id && handle.map_id == locked_on.map_id
+                })
+                .collect()
+        }
+        SpEffectTarget::Spirit { id } => spiritash::get_spirit(*id).into_iter().collect(),
+    };
+
+    Some(characters)
+}
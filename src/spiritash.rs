@@ -1,7 +1,9 @@
 use crate::{
     player::{get_camera, ChrIns, WorldChrMan},
     reflection::get_instance,
-    util::{get_game_base, get_world_chr_man, OutgoingMessage, Position, GAMEPUSH_SEND},
+    util::{
+        apply_speffect, get_game_base, get_world_chr_man, OutgoingMessage, Position, GAMEPUSH_SEND,
+    },
 };
 use lazy_static::lazy_static;
 use std::collections::HashMap;
@@ -77,6 +79,45 @@ pub fn get_position() -> Option<Vec<Position>> {
     return Some(positions);
 }
 
+// Finds a summoned spirit by the id it's reported with
+pub fn get_spirit(id: i32) -> Option<*mut ChrIns<'static>> {
+    let world_chr_man = get_world_chr_man().filter(|world_chr_man| *world_chr_man != 0)?;
+
+    unsafe {
+        let buddy_chr_set = world_chr_man + 0x10f90;
+        let mut chr_count = *((buddy_chr_set + 0x20) as *mut u32);
+        if chr_count == 0xffffffff {
+            chr_count = *((buddy_chr_set + 0x10) as *mut u32);
+        }
+
+        let chr_set = *((buddy_chr_set + 0x18) as *mut u64);
+        if chr_set == 0 {
+            return None;
+        }
+
+        for i in 1..chr_count {
+            let chrins_ptr = *((chr_set + (i * 0x10) as u64) as *mut u64);
+            if chrins_ptr != 0 {
+                let chrins = chrins_ptr as *mut ChrIns;
+                if (*chrins).vftable == 0 {
+                    continue;
+                }
+
+                //check the teamtype is spiritash
+                if (*chrins).team_type != 0x2f {
+                    continue;
+                }
+
+                if (*chrins).field_ins_handle.instance_id == id {
+                    return Some(chrins);
+                }
+            }
+        }
+    }
+
+    None
+}
+
 lazy_static! {
     static ref LAST_SPIRIT_CHECK: Mutex<HashMap<i32, u32>> = Mutex::new(HashMap::new());
 }
@@ -179,9 +220,6 @@ pub fn get_status() {
             return true;
         });
 
-        let apply_speffect_fn =
-            unsafe { std::mem::transmute::<usize, extern "C" fn(u64, u32, u8)>(base + 0x3e8cf0) };
-
         for (id, (chrins, hp)) in cur_spirit_check {
             //newly summoned. didn't exist before, does now with hp
             if !last_check.contains_key(&id) && hp > 0 {
@@ -205,7 +243,7 @@ pub fn get_status() {
                 //hacks!
                 unsafe {
                     //apply the host mirror speffect to the spirit, to remove the blue glow
-                    apply_speffect_fn(chrins as u64, 360800, 1);
+                    apply_speffect(chrins as u64, 360800);
                     //we need to save off the original hp base, stick it in here
                     (*chrins).module_container.data.recoverable_hp_left1 =
                         (*chrins).module_container.data.hp_base as f32;
@@ -1,4 +1,5 @@
-use crate::player::{get_camera, GameDataMan};
+use crate::difficulty::ScalingRule;
+use crate::player::{get_camera, ChrIns, GameDataMan, MapId};
 use crate::reflection::SectionLookupError;
 use crate::spiritash;
 use broadsword::runtime;
@@ -8,6 +9,7 @@ use serde::Serialize;
 use std::sync::mpsc::Sender;
 use std::sync::LazyLock;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{ops, slice};
 use widestring::U16CString;
 
@@ -15,7 +17,78 @@ use widestring::U16CString;
 #[serde(tag = "type")]
 pub enum OutgoingMessage {
     BloodMessageEvent {
+        id: u16,
         text: String,
+        map_id: MapId,
+        x: f32,
+        y: f32,
+        z: f32,
+        read_count: u32,
+        trigger: ReadTrigger,
+    },
+    BloodMessageSpawned {
+        id: u16,
+        text: String,
+        tag: Option<String>,
+    },
+    BloodMessageList {
+        messages: Vec<BloodMessageInfo>,
+    },
+    BloodMessageExpired {
+        id: u16,
+        text: String,
+        reason: ExpiryReason,
+    },
+    WorldMessageList {
+        messages: Vec<WorldMessageInfo>,
+    },
+    MessageVisualList {
+        visuals: Vec<MessageVisualInfo>,
+    },
+    Difficulty {
+        level: u32,
+    },
+    ScalingRuleAdded {
+        id: u32,
+    },
+    ScalingRuleList {
+        rules: Vec<ScalingRuleInfo>,
+    },
+    DifficultyChanged {
+        old: u32,
+        new: u32,
+        source: DifficultySource,
+    },
+    DynamicDifficultyEvent {
+        old: u32,
+        new: u32,
+        reason: DifficultyChangeReason,
+    },
+    DifficultyModifierAdded {
+        id: u32,
+        level: u32,
+        remaining_seconds: u64,
+    },
+    DifficultyModifierExpired {
+        id: u32,
+        level: u32,
+        restored: Option<u32>,
+    },
+    DifficultyModifierList {
+        base: Option<u32>,
+        modifiers: Vec<DifficultyModifierInfo>,
+    },
+    SpEffectApplied {
+        speffect: u32,
+        count: usize,
+    },
+    SpEffectRemoved {
+        speffect: u32,
+        count: usize,
+    },
+    CommandFailed {
+        command: String,
+        reason: String,
     },
     PositionEvent {
         player: CameraInfo,
@@ -47,6 +120,94 @@ pub struct Position {
     pub z: f32,
 }
 
+#[derive(Debug, Serialize)]
+pub struct MessageVisualInfo {
+    pub name: String,
+    pub id: i32,
+    pub description: String,
+}
+
+/// What made us count a blood message as read
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum ReadTrigger {
+    /// The game looked the text up, i.e. the message was opened
+    Interaction,
+    /// The player walked up to it. Positions are relative to the map block, so this only fires for
+    /// messages in the player's own block. Messages in a neighbouring open world block are still
+    /// loaded, but only count as read through Interaction.
+    Proximity,
+}
+
+/// Why a blood message went away without the client asking for it
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum ExpiryReason {
+    /// Its time to live ran out
+    Ttl,
+    /// It was the oldest message when the live message limit was hit
+    Evicted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BloodMessageInfo {
+    pub id: u16,
+    pub text: String,
+    pub tag: Option<String>,
+    pub map_id: MapId,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub angle: f32,
+    pub msg_visual: i32,
+    pub spawned_at: u64,
+    pub read_count: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorldMessageInfo {
+    pub template_id: u16,
+    pub text: Option<String>,
+    pub map_id: MapId,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub angle: f32,
+    pub distance: Option<f32>,
+    pub is_mod: bool,
+    pub online: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DifficultyModifierInfo {
+    pub id: u32,
+    pub level: u32,
+    pub remaining_seconds: u64,
+}
+
+// What changed the difficulty level
+#[derive(Debug, Serialize, Clone, Copy)]
+pub enum DifficultySource {
+    Command,
+    TimedModifier,
+    ModifierExpired,
+    Dynamic,
+}
+
+// Why dynamic difficulty moved the level
+#[derive(Debug, Serialize, Clone, Copy)]
+#[serde(tag = "type")]
+pub enum DifficultyChangeReason {
+    Deaths { count: u32 },
+    Survived { seconds: u64 },
+    BossKilled { npc_id: i32 },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScalingRuleInfo {
+    pub id: u32,
+    #[serde(flatten)]
+    pub rule: ScalingRule,
+}
+
 #[derive(Debug, Serialize)]
 pub struct CameraInfo {
     pub x: f32,
@@ -63,6 +224,17 @@ pub struct CameraInfo {
     pub c3: f32,
 }
 
+/// Pushes a message to the connected client, if there is one.
+pub fn send_message(msg: OutgoingMessage) {
+    if let Some(sender) = GAMEPUSH_SEND.lock().unwrap().as_ref() {
+        sender
+            .send(tungstenite::Message::Text(
+                serde_json::to_string(&msg).unwrap(),
+            ))
+            .expect("Send failed");
+    }
+}
+
 pub fn report_position() {
     if let Some(cam) = get_camera() {
         if let Some(spirits) = spiritash::get_position() {
@@ -121,7 +293,50 @@ pub fn get_game_base() -> Option<usize> {
     None
 }
 
-#[allow(dead_code)]
+// Applies a SpEffect to a character with the game's own function
+pub fn apply_speffect(chr_ins: u64, speffect: u32) {
+    let base = get_game_base().expect("Could not acquire game base");
+
+    let apply_speffect_fn =
+        unsafe { std::mem::transmute::<usize, extern "C" fn(u64, u32, u8)>(base + 0x3e8cf0) };
+    apply_speffect_fn(chr_ins, speffect, 1);
+}
+
+// Team types on the player's side: the host, white phantoms, friendly NPCs, NPCs fighting
+// alongside the player and spirit ashes
+const FRIENDLY_TEAM_TYPES: [u8; 5] = [1, 2, 8, 12, 0x2f];
+
+// Whether a character is out to get the player. Players and phantoms are PlayerIns, everything
+// else is an EnemyIns whose team says which side it's on.
+pub fn is_hostile(chr_ins: &ChrIns) -> bool {
+    let base = get_game_base().expect("Could not acquire game base");
+
+    chr_ins.vftable == base + 0x2a44010 && !FRIENDLY_TEAM_TYPES.contains(&chr_ins.team_type)
+}
+
+fn get_chr_position(chr_ins: &ChrIns) -> (f32, f32, f32) {
+    let position = &chr_ins.module_container.physics.unk70_position;
+    (position.0, position.1, position.2)
+}
+
+pub fn distance(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+// Whether a character is within radius of another. Positions are relative to the map, so only
+// characters in the same map count.
+pub fn within_radius(chr_ins: &ChrIns, other: &ChrIns, radius: f32) -> bool {
+    chr_ins.map_id_1 == other.map_id_1
+        && distance(get_chr_position(chr_ins), get_chr_position(other)) <= radius
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
 pub fn display_custom_text_message(text: String) {
     let base = get_game_base().expect("Could not acquire game base");
 